@@ -0,0 +1,39 @@
+extern crate rng;
+
+use rng::chacha20::{ChaCha20Generator, ChaCha20Stream, ChaCha20WideStream};
+use rng::xorshift::{Xoroshiro1024Generator, Xoroshiro1024Stream};
+
+use std::io::{Read};
+use std::time::{Instant};
+
+const BUF_LEN: usize = 1 << 20;
+const ROUNDS: usize = 64;
+
+fn bench<S: Read + Iterator<Item=u8>>(name: &str, mut stream: S) {
+  let mut buf = vec![0_u8; BUF_LEN];
+  let t0 = Instant::now();
+  for _ in 0 .. ROUNDS {
+    for x in buf.iter_mut() {
+      *x = stream.next().unwrap();
+    }
+  }
+  let bytewise_s = t0.elapsed().as_secs_f64();
+  let t0 = Instant::now();
+  for _ in 0 .. ROUNDS {
+    stream.read_exact(&mut buf).unwrap();
+  }
+  let bulk_s = t0.elapsed().as_secs_f64();
+  let mib = (BUF_LEN * ROUNDS) as f64 / (1 << 20) as f64;
+  println!("{:<16} bytewise: {:>9.1} MiB/s  read: {:>9.1} MiB/s  ({:.1}x)",
+      name, mib / bytewise_s, mib / bulk_s, bytewise_s / bulk_s);
+}
+
+fn main() {
+  bench("chacha20", ChaCha20Stream::new(ChaCha20Generator::new_default(&[0_u8; 32][..], 0, 0)));
+  bench("chacha20 (wide)", ChaCha20WideStream::new(ChaCha20Generator::new_default(&[0_u8; 32][..], 0, 0)));
+  let mut state = [0; 16];
+  for (k, x) in state.iter_mut().enumerate() {
+    *x = k as u64 + 1;
+  }
+  bench("xoroshiro1024", Xoroshiro1024Stream::new(Xoroshiro1024Generator::from(state)));
+}
@@ -1,8 +1,15 @@
-use crate::{Generator, Buffer32};
+use crate::{Generator, Buffer32, Reseed, Zeroize, seek_offset, seek_offset_from_u64_end, zero_volatile};
+use crate::chacha20_simd::{chacha_next4, chacha_next8};
+use crate::snapshot::{Snapshot, invalid_snapshot, restore_u32s, save_u32s};
 
 use byteorder::{ReadBytesExt, LittleEndian as LE};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+#[cfg(feature = "serde")]
+use serde::de::{Error as DeError};
 
-use std::io::{Read, Seek, SeekFrom, Cursor, Error as IoError};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::io::{Read, Seek, SeekFrom, Cursor, Error as IoError, ErrorKind as IoErrorKind};
 
 #[inline]
 pub fn chacha20_quarter_round(a: &mut u32, b: &mut u32, c: &mut u32, d: &mut u32) {
@@ -12,108 +19,675 @@ pub fn chacha20_quarter_round(a: &mut u32, b: &mut u32, c: &mut u32, d: &mut u32
   *c = (*c).wrapping_add(*d); *b = (*b ^ *c).rotate_left(7);
 }
 
-pub fn chacha20_next(state: &mut [u32; 16], out: &mut [u32; 16]) {
-  let mut x = *state;
-  let mut ctr = (x[12] as u64) | ((x[13] as u64) << 32);
-  {
-    let [mut x0, mut x1, mut x2, mut x3,
-         mut x4, mut x5, mut x6, mut x7,
-         mut x8, mut x9, mut x10, mut x11,
-         mut x12, mut x13, mut x14, mut x15] = x;
-    for _ in 0 .. 10 {
-      chacha20_quarter_round(&mut x0, &mut x4, &mut x8, &mut x12);
-      chacha20_quarter_round(&mut x1, &mut x5, &mut x9, &mut x13);
-      chacha20_quarter_round(&mut x2, &mut x6, &mut x10, &mut x14);
-      chacha20_quarter_round(&mut x3, &mut x7, &mut x11, &mut x15);
-      chacha20_quarter_round(&mut x0, &mut x5, &mut x10, &mut x15);
-      chacha20_quarter_round(&mut x1, &mut x6, &mut x11, &mut x12);
-      chacha20_quarter_round(&mut x2, &mut x7, &mut x8, &mut x13);
-      chacha20_quarter_round(&mut x3, &mut x4, &mut x9, &mut x14);
-    }
-    x = [x0, x1, x2, x3,
-         x4, x5, x6, x7,
-         x8, x9, x10, x11,
-         x12, x13, x14, x15];
+#[inline]
+pub fn chacha_rounds(double_rounds: usize, x: &mut [u32; 16]) {
+  let [mut x0, mut x1, mut x2, mut x3,
+       mut x4, mut x5, mut x6, mut x7,
+       mut x8, mut x9, mut x10, mut x11,
+       mut x12, mut x13, mut x14, mut x15] = *x;
+  for _ in 0 .. double_rounds {
+    chacha20_quarter_round(&mut x0, &mut x4, &mut x8, &mut x12);
+    chacha20_quarter_round(&mut x1, &mut x5, &mut x9, &mut x13);
+    chacha20_quarter_round(&mut x2, &mut x6, &mut x10, &mut x14);
+    chacha20_quarter_round(&mut x3, &mut x7, &mut x11, &mut x15);
+    chacha20_quarter_round(&mut x0, &mut x5, &mut x10, &mut x15);
+    chacha20_quarter_round(&mut x1, &mut x6, &mut x11, &mut x12);
+    chacha20_quarter_round(&mut x2, &mut x7, &mut x8, &mut x13);
+    chacha20_quarter_round(&mut x3, &mut x4, &mut x9, &mut x14);
   }
+  *x = [x0, x1, x2, x3,
+        x4, x5, x6, x7,
+        x8, x9, x10, x11,
+        x12, x13, x14, x15];
+}
+
+/// Computes the block for the current state without advancing the counter.
+#[inline]
+pub fn chacha_block(double_rounds: usize, state: &[u32; 16], out: &mut [u32; 16]) {
+  let mut x = *state;
+  chacha_rounds(double_rounds, &mut x);
   for k in 0 .. 16 {
     x[k] = x[k].wrapping_add(state[k]);
   }
-  ctr += 1;
+  *out = x;
+}
+
+/// Computes the block for the current state and increments the counter,
+/// wrapping at 2^64; the generators detect the wrap as the end of the
+/// keystream.
+pub fn chacha_next(double_rounds: usize, state: &mut [u32; 16], out: &mut [u32; 16]) {
+  chacha_block(double_rounds, state, out);
+  let ctr = ((state[12] as u64) | ((state[13] as u64) << 32)).wrapping_add(1);
   state[12] = ctr as u32;
   state[13] = (ctr >> 32) as u32;
-  *out = x;
 }
 
-pub struct ChaCha20Generator {
-  state: [u32; 16],
+#[inline]
+pub fn chacha8_next(state: &mut [u32; 16], out: &mut [u32; 16]) {
+  chacha_next(4, state, out)
+}
+
+#[inline]
+pub fn chacha12_next(state: &mut [u32; 16], out: &mut [u32; 16]) {
+  chacha_next(6, state, out)
+}
+
+#[inline]
+pub fn chacha20_next(state: &mut [u32; 16], out: &mut [u32; 16]) {
+  chacha_next(10, state, out)
+}
+
+pub const CHACHA_STREAM_LEN: u128 = 1 << 70;
+
+macro_rules! chacha_stream_seek_u128 {
+  ($gen:ident) => {
+    impl Buffer32<$gen, [u32; 16]> {
+      /// Seeks to an arbitrary byte offset within the full 2^70-byte
+      /// keystream, which is not addressable through `Seek` for offsets past
+      /// 2^64.
+      pub fn seek_u128(&mut self, p: u128) -> Result<u128, IoError> {
+        if p >= CHACHA_STREAM_LEN {
+          return Err(IoError::new(IoErrorKind::InvalidInput, "seek position overflows the ChaCha block counter"));
+        }
+        let p_rem = (p % 64) as usize;
+        self.gen.set_block_counter((p / 64) as u64);
+        self.cur = match p_rem {
+          0 => 64,
+          _ => {
+            self.gen.next_gen(&mut self.ubuf);
+            p_rem
+          }
+        };
+        Ok(p)
+      }
+
+      pub fn stream_position_u128(&self) -> u128 {
+        self.gen.block_position_u128() * 64 - (64 - self.cur) as u128
+      }
+    }
+  };
+}
+
+/// Buffer of several consecutive keystream blocks, for use with `Buffer32`
+/// to amortize the multi-block generators.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Blocks<U>(pub U);
+
+impl Default for Blocks<[u32; 64]> {
+  fn default() -> Blocks<[u32; 64]> {
+    Blocks([0; 64])
+  }
 }
 
-impl From<[u32; 16]> for ChaCha20Generator {
-  fn from(state: [u32; 16]) -> ChaCha20Generator {
-    ChaCha20Generator{state}
+impl Default for Blocks<[u32; 128]> {
+  fn default() -> Blocks<[u32; 128]> {
+    Blocks([0; 128])
   }
 }
 
-impl ChaCha20Generator {
-  pub fn from_parts<C: AsRef<[u8]>, K: AsRef<[u8]>>(constant_buf: C, key_buf: K, nonce: u64, ctr: u64) -> ChaCha20Generator {
+impl<U: Zeroize> Zeroize for Blocks<U> {
+  fn zeroize(&mut self) {
+    self.0.zeroize();
+  }
+}
+
+/// Serialized as a sequence of words, since serde only supports arrays of
+/// up to 32 elements.
+#[cfg(feature = "serde")]
+impl<U: AsRef<[u32]>> Serialize for Blocks<U> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(self.0.as_ref())
+  }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, U: AsMut<[u32]>> Deserialize<'de> for Blocks<U> where Blocks<U>: Default {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Blocks<U>, D::Error> {
+    let words = Vec::<u32>::deserialize(deserializer)?;
+    let mut blocks = Blocks::<U>::default();
+    let buf = blocks.as_mut();
+    if words.len() != buf.len() {
+      return Err(D::Error::invalid_length(words.len(), &"the number of words in the block buffer"));
+    }
+    buf.copy_from_slice(&words);
+    Ok(blocks)
+  }
+}
+
+impl<U: AsMut<[u32]>> AsMut<[u32]> for Blocks<U> {
+  fn as_mut(&mut self) -> &mut [u32] {
+    self.0.as_mut()
+  }
+}
+
+impl<U: AsRef<[u32]>> AsRef<[u32]> for Blocks<U> {
+  #[inline]
+  fn as_ref(&self) -> &[u32] {
+    self.0.as_ref()
+  }
+}
+
+fn chacha_reseed_key<R: Read>(state: &mut [u32; 16], mut seed: R) -> Result<(), IoError> {
+  let mut buf = [0; 32];
+  seed.read_exact(&mut buf)?;
+  for (k, w) in state[4 .. 12].iter_mut().zip(buf.chunks_exact(4)) {
+    *k ^= u32::from_le_bytes([w[0], w[1], w[2], w[3]]);
+  }
+  zero_volatile(&mut buf);
+  Ok(())
+}
+
+macro_rules! chacha_generator {
+  ($gen:ident, $stream:ident, $wide_stream:ident, $next:ident, $double_rounds:expr) => {
+    // Key comparisons are not constant-time, so equality here and in the
+    // other ChaCha generators is for tests only.
+    #[cfg_attr(test, derive(PartialEq, Eq))]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+    pub struct $gen {
+      state: [u32; 16],
+      end: bool,
+    }
+
+    impl From<[u32; 16]> for $gen {
+      fn from(state: [u32; 16]) -> $gen {
+        $gen{state, end: false}
+      }
+    }
+
+    impl $gen {
+      pub fn from_parts<C: AsRef<[u8]>, K: AsRef<[u8]>>(constant_buf: C, key_buf: K, nonce: u64, ctr: u64) -> $gen {
+        let mut state = [0; 16];
+        let mut constant = Cursor::new(constant_buf.as_ref());
+        for x in state[0 .. 4].iter_mut() {
+          *x = constant.read_u32::<LE>().unwrap();
+        }
+        let mut key = Cursor::new(key_buf.as_ref());
+        for x in state[4 .. 12].iter_mut() {
+          *x = key.read_u32::<LE>().unwrap();
+        }
+        state[12] = ctr as u32;
+        state[13] = (ctr >> 32) as u32;
+        state[14] = nonce as u32;
+        state[15] = (nonce >> 32) as u32;
+        $gen{state, end: false}
+      }
+
+      pub fn new_default<R: Read>(mut key_seed: R, nonce: u64, ctr: u64) -> $gen {
+        let mut key_buf = [0; 32];
+        key_seed.read_exact(&mut key_buf).unwrap();
+        let gen = $gen::from_parts(b"extend 32-byte k", &key_buf, nonce, ctr);
+        zero_volatile(&mut key_buf);
+        gen
+      }
+
+      #[inline]
+      pub fn block_counter(&self) -> u64 {
+        (self.state[12] as u64) | ((self.state[13] as u64) << 32)
+      }
+
+      #[inline]
+      pub fn set_block_counter(&mut self, ctr: u64) {
+        self.state[12] = ctr as u32;
+        self.state[13] = (ctr >> 32) as u32;
+        self.end = false;
+      }
+
+      /// The block counter, or 2^64 once the keystream is exhausted.
+      #[inline]
+      fn block_position_u128(&self) -> u128 {
+        match self.end {
+          false => self.block_counter() as u128,
+          true  => 1 << 64,
+        }
+      }
+
+      /// Fails unless `nblocks` more blocks fit before the end of the
+      /// keystream; a multi-block request never wraps the counter partway.
+      fn check_blocks(&self, nblocks: u64) -> Result<(), IoError> {
+        if self.end || self.block_counter().checked_add(nblocks - 1).is_none() {
+          return Err(IoError::new(IoErrorKind::UnexpectedEof, "ChaCha block counter overflow"));
+        }
+        Ok(())
+      }
+    }
+
+    impl Generator<[u32; 16]> for $gen {
+      #[inline]
+      fn next_gen(&mut self, out: &mut [u32; 16]) {
+        self.try_next_gen(out).unwrap();
+      }
+
+      #[inline]
+      fn try_next_gen(&mut self, out: &mut [u32; 16]) -> Result<(), IoError> {
+        self.check_blocks(1)?;
+        $next(&mut self.state, out);
+        self.end = self.block_counter() == 0;
+        Ok(())
+      }
+    }
+
+    impl Zeroize for $gen {
+      fn zeroize(&mut self) {
+        self.state.zeroize();
+      }
+    }
+
+    /// XORs 32 bytes of `seed` into the key; the nonce and the block counter
+    /// are kept.
+    impl Reseed for $gen {
+      fn reseed<R: Read>(&mut self, seed: R) -> Result<(), IoError> {
+        chacha_reseed_key(&mut self.state, seed)
+      }
+    }
+
+    impl Snapshot for $gen {
+      const TAG: &'static str = stringify!($gen);
+
+      fn save_state(&self, out: &mut Vec<u8>) {
+        save_u32s(out, &self.state);
+        out.push(self.end as u8);
+      }
+
+      fn restore_state(state: &mut &[u8]) -> Result<$gen, IoError> {
+        let mut s = [0; 16];
+        restore_u32s(state, &mut s)?;
+        let end = match state.read_u8()? {
+          0 => false,
+          1 => true,
+          _ => return Err(invalid_snapshot(concat!(stringify!($gen), " snapshot has an invalid end flag"))),
+        };
+        Ok($gen{state: s, end})
+      }
+    }
+
+    /// The key is redacted.
+    impl Debug for $gen {
+      fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct(stringify!($gen))
+          .field("key", &"<redacted>")
+          .field("ctr", &self.block_counter())
+          .field("nonce", &((self.state[14] as u64) | ((self.state[15] as u64) << 32)))
+          .field("end", &self.end)
+          .finish()
+      }
+    }
+
+    impl Generator<[u32; 64]> for $gen {
+      #[inline]
+      fn next_gen(&mut self, out: &mut [u32; 64]) {
+        self.try_next_gen(out).unwrap();
+      }
+
+      #[inline]
+      fn try_next_gen(&mut self, out: &mut [u32; 64]) -> Result<(), IoError> {
+        self.check_blocks(4)?;
+        chacha_next4($double_rounds, &mut self.state, out);
+        self.end = self.block_counter() == 0;
+        Ok(())
+      }
+    }
+
+    impl Generator<[u32; 128]> for $gen {
+      #[inline]
+      fn next_gen(&mut self, out: &mut [u32; 128]) {
+        self.try_next_gen(out).unwrap();
+      }
+
+      #[inline]
+      fn try_next_gen(&mut self, out: &mut [u32; 128]) -> Result<(), IoError> {
+        self.check_blocks(8)?;
+        chacha_next8($double_rounds, &mut self.state, out);
+        self.end = self.block_counter() == 0;
+        Ok(())
+      }
+    }
+
+    impl<U> Generator<Blocks<U>> for $gen where $gen: Generator<U> {
+      #[inline]
+      fn next_gen(&mut self, out: &mut Blocks<U>) {
+        self.next_gen(&mut out.0);
+      }
+
+      #[inline]
+      fn try_next_gen(&mut self, out: &mut Blocks<U>) -> Result<(), IoError> {
+        self.try_next_gen(&mut out.0)
+      }
+    }
+
+    /// Seek positions are byte offsets into the keystream. Only the first
+    /// 2^64 bytes (block counters below 2^58) are addressable this way, and
+    /// `SeekFrom::End` is relative to that boundary.
+    impl Seek for $gen {
+      fn seek(&mut self, pos: SeekFrom) -> Result<u64, IoError> {
+        match pos {
+          SeekFrom::Start(p) => {
+            if p % 64 != 0 {
+              return Err(IoError::new(IoErrorKind::InvalidInput, "seek position is not aligned to a 64-byte block"));
+            }
+            self.set_block_counter(p / 64);
+            Ok(p)
+          }
+          SeekFrom::Current(off) => {
+            let p = seek_offset(self.stream_position()?, off)?;
+            self.seek(SeekFrom::Start(p))
+          }
+          SeekFrom::End(off) => {
+            let p = seek_offset_from_u64_end(off)?;
+            self.seek(SeekFrom::Start(p))
+          }
+        }
+      }
+
+      fn stream_position(&mut self) -> Result<u64, IoError> {
+        if !self.end {
+          if let Some(p) = self.block_counter().checked_mul(64) {
+            return Ok(p);
+          }
+        }
+        Err(IoError::new(IoErrorKind::Other, "stream position does not fit in 64 bits"))
+      }
+    }
+
+    pub type $stream = Buffer32<$gen, [u32; 16]>;
+    pub type $wide_stream = Buffer32<$gen, Blocks<[u32; 128]>>;
+
+    chacha_stream_seek_u128!($gen);
+  };
+}
+
+chacha_generator!(ChaCha8Generator, ChaCha8Stream, ChaCha8WideStream, chacha8_next, 4);
+chacha_generator!(ChaCha12Generator, ChaCha12Stream, ChaCha12WideStream, chacha12_next, 6);
+chacha_generator!(ChaCha20Generator, ChaCha20Stream, ChaCha20WideStream, chacha20_next, 10);
+
+/// Length in bytes of the RFC 8439 keystream, i.e. 2^32 blocks (256 GiB).
+pub const CHACHA20_IETF_STREAM_LEN: u64 = 1 << 38;
+
+/// ChaCha20 with the RFC 8439 state layout: a 32-bit block counter in word
+/// 12 and a 96-bit nonce in words 13-15. Generating past the end of the
+/// 256 GiB keystream is an error rather than wrapping the counter.
+#[cfg_attr(test, derive(PartialEq, Eq))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ChaCha20IetfGenerator {
+  state: [u32; 16],
+  end: bool,
+}
+
+impl ChaCha20IetfGenerator {
+  pub fn from_parts<K: AsRef<[u8]>, N: AsRef<[u8]>>(key_buf: K, nonce_buf: N, ctr: u32) -> ChaCha20IetfGenerator {
     let mut state = [0; 16];
-    let mut constant = Cursor::new(constant_buf.as_ref());
-    for k in 0 .. 4 {
-      state[k] = constant.read_u32::<LE>().unwrap();
+    let mut constant = Cursor::new(b"expand 32-byte k");
+    for x in state[0 .. 4].iter_mut() {
+      *x = constant.read_u32::<LE>().unwrap();
     }
-    drop(constant);
     let mut key = Cursor::new(key_buf.as_ref());
-    for k in 4 .. 12 {
-      state[k] = key.read_u32::<LE>().unwrap();
+    for x in state[4 .. 12].iter_mut() {
+      *x = key.read_u32::<LE>().unwrap();
     }
-    drop(key);
-    state[12] = ctr as u32;
-    state[13] = (ctr >> 32) as u32;
-    state[14] = nonce as u32;
-    state[15] = (nonce >> 32) as u32;
-    ChaCha20Generator{state}
+    state[12] = ctr;
+    let mut nonce = Cursor::new(nonce_buf.as_ref());
+    for x in state[13 .. 16].iter_mut() {
+      *x = nonce.read_u32::<LE>().unwrap();
+    }
+    ChaCha20IetfGenerator{state, end: false}
   }
 
-  pub fn new_default<R: Read>(mut key_seed: R, nonce: u64, ctr: u64) -> ChaCha20Generator {
-    let mut key_buf = [0; 32];
-    key_seed.read_exact(&mut key_buf).unwrap();
-    ChaCha20Generator::from_parts(b"extend 32-byte k", key_buf, nonce, ctr)
+  #[inline]
+  pub fn block_counter(&self) -> u32 {
+    self.state[12]
+  }
+
+  #[inline]
+  pub fn set_block_counter(&mut self, ctr: u32) {
+    self.state[12] = ctr;
+    self.end = false;
   }
 }
 
-impl Generator<[u32; 16]> for ChaCha20Generator {
+impl Zeroize for ChaCha20IetfGenerator {
+  fn zeroize(&mut self) {
+    self.state.zeroize();
+  }
+}
+
+impl Reseed for ChaCha20IetfGenerator {
+  fn reseed<R: Read>(&mut self, seed: R) -> Result<(), IoError> {
+    chacha_reseed_key(&mut self.state, seed)
+  }
+}
+
+impl Snapshot for ChaCha20IetfGenerator {
+  const TAG: &'static str = "ChaCha20IetfGenerator";
+
+  fn save_state(&self, out: &mut Vec<u8>) {
+    save_u32s(out, &self.state);
+    out.push(self.end as u8);
+  }
+
+  fn restore_state(state: &mut &[u8]) -> Result<ChaCha20IetfGenerator, IoError> {
+    let mut s = [0; 16];
+    restore_u32s(state, &mut s)?;
+    let end = match state.read_u8()? {
+      0 => false,
+      1 => true,
+      _ => return Err(invalid_snapshot("ChaCha20IetfGenerator snapshot has an invalid end flag")),
+    };
+    Ok(ChaCha20IetfGenerator{state: s, end})
+  }
+}
+
+/// The key is redacted.
+impl Debug for ChaCha20IetfGenerator {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    f.debug_struct("ChaCha20IetfGenerator")
+      .field("key", &"<redacted>")
+      .field("ctr", &self.block_counter())
+      .field("nonce", &[self.state[13], self.state[14], self.state[15]])
+      .field("end", &self.end)
+      .finish()
+  }
+}
+
+impl Generator<[u32; 16]> for ChaCha20IetfGenerator {
   #[inline]
   fn next_gen(&mut self, out: &mut [u32; 16]) {
-    chacha20_next(&mut self.state, out);
+    self.try_next_gen(out).unwrap();
+  }
+
+  #[inline]
+  fn try_next_gen(&mut self, out: &mut [u32; 16]) -> Result<(), IoError> {
+    if self.end {
+      return Err(IoError::new(IoErrorKind::UnexpectedEof, "ChaCha20 block counter overflow"));
+    }
+    chacha_block(10, &self.state, out);
+    self.state[12] = self.state[12].wrapping_add(1);
+    self.end = self.state[12] == 0;
+    Ok(())
   }
 }
 
-impl Seek for ChaCha20Generator {
+/// `SeekFrom::End` is relative to the end of the 256 GiB keystream.
+impl Seek for ChaCha20IetfGenerator {
   fn seek(&mut self, pos: SeekFrom) -> Result<u64, IoError> {
     match pos {
       SeekFrom::Start(p) => {
-        assert_eq!(p % 64, 0);
-        let ctr = p / 64;
-        self.state[12] = ctr as u32;
-        self.state[13] = (ctr >> 32) as u32;
+        if p % 64 != 0 {
+          return Err(IoError::new(IoErrorKind::InvalidInput, "seek position is not aligned to a 64-byte block"));
+        }
+        if p > CHACHA20_IETF_STREAM_LEN {
+          return Err(IoError::new(IoErrorKind::InvalidInput, "seek position overflows the ChaCha20 block counter"));
+        }
+        self.set_block_counter((p / 64) as u32);
+        self.end = p == CHACHA20_IETF_STREAM_LEN;
         Ok(p)
       }
-      _ => unimplemented!()
+      SeekFrom::Current(off) => {
+        let p = seek_offset(self.stream_position()?, off)?;
+        self.seek(SeekFrom::Start(p))
+      }
+      SeekFrom::End(off) => {
+        let p = seek_offset(CHACHA20_IETF_STREAM_LEN, off)?;
+        self.seek(SeekFrom::Start(p))
+      }
+    }
+  }
+
+  fn stream_position(&mut self) -> Result<u64, IoError> {
+    if self.end {
+      return Ok(CHACHA20_IETF_STREAM_LEN);
     }
+    Ok(self.block_counter() as u64 * 64)
+  }
+}
+
+pub type ChaCha20IetfStream = Buffer32<ChaCha20IetfGenerator, [u32; 16]>;
+
+/// HChaCha20 subkey derivation, as used by XChaCha20: runs the ChaCha20
+/// rounds over the key and a 16-byte nonce without the final addition, and
+/// returns words 0-3 and 12-15 of the result.
+pub fn hchacha20<K: AsRef<[u8]>, N: AsRef<[u8]>>(key_buf: K, nonce_buf: N) -> [u8; 32] {
+  let mut x = [0; 16];
+  let mut constant = Cursor::new(b"expand 32-byte k");
+  for w in x[0 .. 4].iter_mut() {
+    *w = constant.read_u32::<LE>().unwrap();
+  }
+  let mut key = Cursor::new(key_buf.as_ref());
+  for w in x[4 .. 12].iter_mut() {
+    *w = key.read_u32::<LE>().unwrap();
+  }
+  let mut nonce = Cursor::new(nonce_buf.as_ref());
+  for w in x[12 .. 16].iter_mut() {
+    *w = nonce.read_u32::<LE>().unwrap();
+  }
+  chacha_rounds(10, &mut x);
+  let mut subkey = [0; 32];
+  for (dst, &w) in subkey.chunks_exact_mut(4).zip(x[0 .. 4].iter().chain(x[12 .. 16].iter())) {
+    dst.copy_from_slice(&w.to_le_bytes());
+  }
+  zero_volatile(&mut x);
+  subkey
+}
+
+/// XChaCha20 with a 192-bit nonce: the first 16 bytes of the nonce derive a
+/// subkey via HChaCha20, and the last 8 bytes are the ChaCha20 nonce.
+#[cfg_attr(test, derive(PartialEq, Eq))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct XChaCha20Generator {
+  inner: ChaCha20Generator,
+}
+
+impl XChaCha20Generator {
+  pub fn from_parts<K: AsRef<[u8]>, N: AsRef<[u8]>>(key_buf: K, nonce_buf: N, ctr: u64) -> XChaCha20Generator {
+    let nonce_buf = nonce_buf.as_ref();
+    assert_eq!(nonce_buf.len(), 24);
+    let mut subkey = hchacha20(key_buf, &nonce_buf[ .. 16]);
+    let nonce = Cursor::new(&nonce_buf[16 .. ]).read_u64::<LE>().unwrap();
+    let inner = ChaCha20Generator::from_parts(b"expand 32-byte k", &subkey[..], nonce, ctr);
+    zero_volatile(&mut subkey);
+    XChaCha20Generator{inner}
+  }
+
+  #[inline]
+  pub fn block_counter(&self) -> u64 {
+    self.inner.block_counter()
+  }
+
+  #[inline]
+  pub fn set_block_counter(&mut self, ctr: u64) {
+    self.inner.set_block_counter(ctr);
+  }
+
+  #[inline]
+  fn block_position_u128(&self) -> u128 {
+    self.inner.block_position_u128()
+  }
+}
+
+impl Zeroize for XChaCha20Generator {
+  fn zeroize(&mut self) {
+    self.inner.zeroize();
+  }
+}
+
+impl Reseed for XChaCha20Generator {
+  fn reseed<R: Read>(&mut self, seed: R) -> Result<(), IoError> {
+    self.inner.reseed(seed)
+  }
+}
+
+/// The snapshot holds the derived ChaCha20 state, not the original key.
+impl Snapshot for XChaCha20Generator {
+  const TAG: &'static str = "XChaCha20Generator";
+
+  fn save_state(&self, out: &mut Vec<u8>) {
+    self.inner.save_state(out);
+  }
+
+  fn restore_state(state: &mut &[u8]) -> Result<XChaCha20Generator, IoError> {
+    Ok(XChaCha20Generator{inner: ChaCha20Generator::restore_state(state)?})
+  }
+}
+
+/// The key, including the derived subkey, is redacted.
+impl Debug for XChaCha20Generator {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    f.debug_struct("XChaCha20Generator").field("inner", &self.inner).finish()
+  }
+}
+
+impl Generator<[u32; 16]> for XChaCha20Generator {
+  #[inline]
+  fn next_gen(&mut self, out: &mut [u32; 16]) {
+    self.inner.next_gen(out);
+  }
+
+  #[inline]
+  fn try_next_gen(&mut self, out: &mut [u32; 16]) -> Result<(), IoError> {
+    self.inner.try_next_gen(out)
+  }
+}
+
+impl Seek for XChaCha20Generator {
+  fn seek(&mut self, pos: SeekFrom) -> Result<u64, IoError> {
+    self.inner.seek(pos)
   }
 
   fn stream_position(&mut self) -> Result<u64, IoError> {
-    let ctr = (self.state[12] as u64) | ((self.state[13] as u64) << 32);
-    Ok(ctr * 64)
+    self.inner.stream_position()
   }
 }
 
-pub type ChaCha20Stream = Buffer32<ChaCha20Generator, [u32; 16]>;
+pub type XChaCha20Stream = Buffer32<XChaCha20Generator, [u32; 16]>;
+
+chacha_stream_seek_u128!(XChaCha20Generator);
 
 #[cfg(test)]
 mod tests {
-use super::{ChaCha20Generator, chacha20_quarter_round};
-use crate::{Generator};
+use super::{
+  ChaCha8Generator, ChaCha8Stream, ChaCha12Generator, ChaCha12Stream,
+  ChaCha20Generator, ChaCha20Stream, CHACHA_STREAM_LEN, chacha20_quarter_round,
+  ChaCha20IetfGenerator, ChaCha20IetfStream, CHACHA20_IETF_STREAM_LEN,
+  XChaCha20Generator, XChaCha20Stream, hchacha20,
+  ChaCha8WideStream, ChaCha20WideStream,
+};
+use crate::{Generator, hex_bytes};
+
+use std::io::{Read, Seek, SeekFrom, ErrorKind as IoErrorKind};
+
+fn test_key_buf() -> [u8; 32] {
+  let mut key_buf: [u8; 32] = [0; 32];
+  for k in 0 .. 32 {
+    key_buf[k as usize] = k;
+  }
+  key_buf
+}
+
+const TC8_KEY: [u8; 32] = [
+    0xc4, 0x6e, 0xc1, 0xb1, 0x8c, 0xe8, 0xa8, 0x78,
+    0x72, 0x5a, 0x37, 0xe7, 0x80, 0xdf, 0xb7, 0x35,
+    0x1f, 0x68, 0xed, 0x2e, 0x19, 0x4c, 0x79, 0xfb,
+    0xc6, 0xae, 0xbe, 0xe1, 0xa6, 0x67, 0x97, 0x5d,
+];
+const TC8_NONCE: u64 = 0x2182_68cf_d531_da1a;
 
 #[test]
 fn test_chacha20_ietf_test_vector_2_1_1() {
@@ -162,4 +736,362 @@ fn test_chacha20_ietf_test_vector_2_3_1() {
   assert_eq!(out[14], 0xe883d0cb);
   assert_eq!(out[15], 0x4e3c50a2);
 }
+
+#[test]
+fn test_chacha20_stream_seek_current_end() {
+  let mut stream = ChaCha20Stream::new(ChaCha20Generator::new_default(&test_key_buf()[..], 0, 0));
+  let mut expected = [0; 300];
+  stream.read_exact(&mut expected).unwrap();
+  assert_eq!(stream.seek(SeekFrom::Current(-200)).unwrap(), 100);
+  let mut buf = [0; 150];
+  stream.read_exact(&mut buf).unwrap();
+  assert_eq!(&buf[..], &expected[100 .. 250]);
+  assert_eq!(stream.seek(SeekFrom::Current(-213)).unwrap(), 37);
+  stream.read_exact(&mut buf[.. 5]).unwrap();
+  assert_eq!(&buf[.. 5], &expected[37 .. 42]);
+  assert!(stream.seek(SeekFrom::Current(-43)).is_err());
+  assert_eq!(stream.stream_position().unwrap(), 42);
+}
+
+#[test]
+fn test_chacha20_stream_seek_end() {
+  let mut stream = ChaCha20Stream::new(ChaCha20Generator::new_default(&test_key_buf()[..], 0, 0));
+  assert!(stream.seek(SeekFrom::End(0)).is_err());
+  let p = stream.seek(SeekFrom::End(-100)).unwrap();
+  assert_eq!(p, u64::MAX - 99);
+  let mut tail = [0; 100];
+  stream.read_exact(&mut tail).unwrap();
+  let mut stream2 = ChaCha20Stream::new(ChaCha20Generator::new_default(&test_key_buf()[..], 0, 0));
+  assert_eq!(stream2.seek(SeekFrom::Start(p)).unwrap(), p);
+  let mut tail2 = [0; 100];
+  stream2.read_exact(&mut tail2).unwrap();
+  assert_eq!(&tail[..], &tail2[..]);
+}
+
+#[test]
+fn test_chacha20_generator_seek_unaligned() {
+  let mut gen = ChaCha20Generator::new_default(&test_key_buf()[..], 0, 0);
+  assert!(gen.seek(SeekFrom::Start(65)).is_err());
+  assert_eq!(gen.seek(SeekFrom::Start(128)).unwrap(), 128);
+  assert_eq!(gen.seek(SeekFrom::Current(-64)).unwrap(), 64);
+  assert_eq!(gen.seek(SeekFrom::End(-64)).unwrap(), u64::MAX - 63);
+}
+
+#[test]
+fn test_chacha20_stream_seek_u128() {
+  let mut stream = ChaCha20Stream::new(ChaCha20Generator::new_default(&test_key_buf()[..], 0, 0));
+  let mut expected = [0; 200];
+  stream.read_exact(&mut expected).unwrap();
+  for &p in &[0_u128, 1, 63, 64, 65, 127, 130] {
+    assert_eq!(stream.seek_u128(p).unwrap(), p);
+    assert_eq!(stream.stream_position_u128(), p);
+    let mut buf = [0; 70];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf[..], &expected[p as usize .. p as usize + 70]);
+    assert_eq!(stream.stream_position_u128(), p + 70);
+  }
+  let p = (1_u128 << 64) + 3;
+  assert_eq!(stream.seek_u128(p).unwrap(), p);
+  assert_eq!(stream.stream_position_u128(), p);
+  let p = CHACHA_STREAM_LEN - 1;
+  assert_eq!(stream.seek_u128(p).unwrap(), p);
+  assert_eq!(stream.stream_position_u128(), p);
+  let mut buf = [0; 2];
+  assert_eq!(stream.read(&mut buf).unwrap(), 1);
+  assert_eq!(stream.stream_position_u128(), CHACHA_STREAM_LEN);
+  assert_eq!(stream.read(&mut buf).unwrap_err().kind(), IoErrorKind::UnexpectedEof);
+  assert_eq!(stream.stream_position_u128(), CHACHA_STREAM_LEN);
+  assert!(stream.seek_u128(CHACHA_STREAM_LEN).is_err());
+  assert_eq!(stream.seek_u128(0).unwrap(), 0);
+  stream.read_exact(&mut buf).unwrap();
+  assert_eq!(&buf[..], &expected[.. 2]);
+}
+
+#[test]
+fn test_chacha20_counter_overflow() {
+  let mut gen = ChaCha20Generator::new_default(&test_key_buf()[..], 0, u64::MAX);
+  let mut out = [0; 16];
+  gen.try_next_gen(&mut out).unwrap();
+  assert_eq!(gen.block_counter(), 0);
+  assert!(gen.stream_position().is_err());
+  assert!(gen.try_next_gen(&mut out).is_err());
+  let mut wide = [0; 128];
+  gen.set_block_counter(u64::MAX - 6);
+  assert!(gen.try_next_gen(&mut wide).is_err());
+  gen.set_block_counter(u64::MAX - 7);
+  gen.try_next_gen(&mut wide).unwrap();
+  assert!(gen.try_next_gen(&mut out).is_err());
+  let mut stream = XChaCha20Stream::new(XChaCha20Generator::from_parts(test_key_buf(), [0; 24], u64::MAX));
+  let mut buf = [0; 100];
+  assert_eq!(stream.read(&mut buf).unwrap(), 64);
+  assert!(stream.read(&mut buf).is_err());
+}
+
+// draft-strombergson-chacha-test-vectors, TC1 (256-bit all-zero key and IV).
+#[test]
+fn test_chacha_rounds_tc1() {
+  let mut out = [0; 64];
+  ChaCha8Stream::new(ChaCha8Generator::from_parts(b"expand 32-byte k", [0; 32], 0, 0)).read_exact(&mut out).unwrap();
+  assert_eq!(&out[..], &hex_bytes("3e00ef2f895f40d67f5bb8e81f09a5a12c840ec3ce9a7f3b181be188ef711a1e984ce172b9216f419f445367456d5619314a42a3da86b001387bfdb80e0cfe42")[..]);
+  ChaCha12Stream::new(ChaCha12Generator::from_parts(b"expand 32-byte k", [0; 32], 0, 0)).read_exact(&mut out).unwrap();
+  assert_eq!(&out[..], &hex_bytes("9bf49a6a0755f953811fce125f2683d50429c3bb49e074147e0089a52eae155f0564f879d27ae3c02ce82834acfa8c793a629f2ca0de6919610be82f411326be")[..]);
+  ChaCha20Stream::new(ChaCha20Generator::from_parts(b"expand 32-byte k", [0; 32], 0, 0)).read_exact(&mut out).unwrap();
+  assert_eq!(&out[..], &hex_bytes("76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586")[..]);
+}
+
+// draft-strombergson-chacha-test-vectors, TC8 (256-bit random key and IV).
+#[test]
+fn test_chacha_rounds_tc8() {
+  let mut gen = ChaCha8Generator::from_parts(b"expand 32-byte k", TC8_KEY, TC8_NONCE, 0);
+  let mut out = [0; 16];
+  gen.next_gen(&mut out);
+  assert_eq!(out, [
+      0xb4518783, 0x8add8d2d, 0x88f4773d, 0x75baa225, 0x7c04f42c, 0x97a508b3, 0x9774f28e, 0xc974e33b,
+      0x0648d86a, 0x7b417158, 0xe634f008, 0xa946fe81, 0x615c7d3f, 0x146630d1, 0x57f2aad4, 0x8bf0cfa7,
+  ]);
+  let mut gen = ChaCha12Generator::from_parts(b"expand 32-byte k", TC8_KEY, TC8_NONCE, 0);
+  gen.next_gen(&mut out);
+  assert_eq!(out, [
+      0x27078214, 0x066dbc84, 0xdc3be7b4, 0x10c08b11, 0x7876793c, 0xe018a96c, 0x25aa8669, 0xc19c7e1f,
+      0x0a9a74b2, 0xb483ee16, 0x992e2d24, 0x207c8db0, 0xbc802b09, 0x28876c46, 0xb3b1613b, 0xabfb0f9d,
+  ]);
+  let mut gen = ChaCha20Generator::from_parts(b"expand 32-byte k", TC8_KEY, TC8_NONCE, 0);
+  gen.next_gen(&mut out);
+  assert_eq!(out, [
+      0xb7893af6, 0xf971225c, 0x54168836, 0x062fa52b, 0x172449ed, 0x002b3092, 0x0af8e8b5, 0xaf73a4e9,
+      0x8f215bc2, 0xfdf09a51, 0x2e3606d4, 0x7fde698d, 0xa604c654, 0x3f350fe0, 0x1b770f11, 0x92aba8dc,
+  ]);
+}
+
+#[test]
+fn test_chacha8_stream_seek() {
+  let mut stream = ChaCha8Stream::new(ChaCha8Generator::from_parts(b"expand 32-byte k", TC8_KEY, TC8_NONCE, 0));
+  let mut expected = [0; 200];
+  stream.read_exact(&mut expected).unwrap();
+  assert_eq!(stream.seek(SeekFrom::Current(-131)).unwrap(), 69);
+  let mut buf = [0; 100];
+  stream.read_exact(&mut buf).unwrap();
+  assert_eq!(&buf[..], &expected[69 .. 169]);
+  assert_eq!(stream.seek_u128(5).unwrap(), 5);
+  stream.read_exact(&mut buf).unwrap();
+  assert_eq!(&buf[..], &expected[5 .. 105]);
+}
+
+fn check_ietf_keystream(key: &[u8], nonce: &[u8], ctr: u32, expected_hex: &str) {
+  let expected = hex_bytes(expected_hex);
+  let mut out = vec![0; expected.len()];
+  ChaCha20IetfStream::new(ChaCha20IetfGenerator::from_parts(key, nonce, ctr)).read_exact(&mut out).unwrap();
+  assert_eq!(out, expected);
+}
+
+fn check_ietf_encrypt(key: &[u8], nonce: &[u8], ctr: u32, plaintext: &[u8], expected_hex: &str) {
+  let expected = hex_bytes(expected_hex);
+  let mut out = plaintext.to_vec();
+  ChaCha20IetfStream::new(ChaCha20IetfGenerator::from_parts(key, nonce, ctr)).apply_keystream(&mut out).unwrap();
+  assert_eq!(out, expected);
+  // Again, split at a boundary that is not block aligned.
+  let mut out = plaintext.to_vec();
+  let (head, tail) = out.split_at_mut(37.min(plaintext.len()));
+  let mut stream = ChaCha20IetfStream::new(ChaCha20IetfGenerator::from_parts(key, nonce, ctr));
+  stream.apply_keystream(head).unwrap();
+  stream.apply_keystream(tail).unwrap();
+  assert_eq!(out, expected);
+}
+
+#[test]
+fn test_chacha20_ietf_rfc8439_2_3_2() {
+  let mut gen = ChaCha20IetfGenerator::from_parts(test_key_buf(), hex_bytes("000000090000004a00000000"), 1);
+  let mut out = [0; 16];
+  gen.next_gen(&mut out);
+  assert_eq!(out, [
+      0xe4e7f110, 0x15593bd1, 0x1fdd0f50, 0xc47120a3, 0xc7f4d1c7, 0x0368c033, 0x9aaa2204, 0x4e6cd4c3,
+      0x466482d2, 0x09aa9f07, 0x05d7c214, 0xa2028bd9, 0xd19c12b5, 0xb94e16de, 0xe883d0cb, 0x4e3c50a2,
+  ]);
+  assert_eq!(gen.block_counter(), 2);
+}
+
+#[test]
+fn test_chacha20_ietf_rfc8439_2_4_2() {
+  check_ietf_encrypt(
+      &test_key_buf(), &hex_bytes("000000000000004a00000000"), 1,
+      b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.",
+      "6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0bf91b65c5524733ab8f593dabcd62b357\
+       1639d624e65152ab8f530c359f0861d807ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab7793736\
+       5af90bbf74a35be6b40b8eedf2785e42874d",
+  );
+}
+
+#[test]
+fn test_chacha20_ietf_rfc8439_a_1() {
+  let mut key = [0; 32];
+  check_ietf_keystream(&key, &[0; 12], 0,
+      "76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586");
+  check_ietf_keystream(&key, &[0; 12], 1,
+      "9f07e7be5551387a98ba977c732d080dcb0f29a048e3656912c6533e32ee7aed29b721769ce64e43d57133b074d839d531ed1f28510afb45ace10a1f4b794d6f");
+  key[31] = 1;
+  check_ietf_keystream(&key, &[0; 12], 1,
+      "3aeb5224ecf849929b9d828db1ced4dd832025e8018b8160b82284f3c949aa5a8eca00bbb4a73bdad192b5c42f73f2fd4e273644c8b36125a64addeb006c13a0");
+  key[31] = 0;
+  key[1] = 0xff;
+  check_ietf_keystream(&key, &[0; 12], 2,
+      "72d54dfbf12ec44b362692df94137f328fea8da73990265ec1bbbea1ae9af0ca13b25aa26cb4a648cb9b9d1be65b2c0924a66c54d545ec1b7374f4872e99f096");
+  check_ietf_keystream(&[0; 32], &hex_bytes("000000000000000000000002"), 0,
+      "c2c64d378cd536374ae204b9ef933fcd1a8b2288b3dfa49672ab765b54ee27c78a970e0e955c14f3a88e741b97c286f75f8fc299e8148362fa198a39531bed6d");
+}
+
+#[test]
+fn test_chacha20_ietf_rfc8439_a_2() {
+  check_ietf_encrypt(&[0; 32], &[0; 12], 0, &[0; 64],
+      "76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586");
+  check_ietf_encrypt(
+      &hex_bytes("0000000000000000000000000000000000000000000000000000000000000001"),
+      &hex_bytes("000000000000000000000002"), 1,
+      b"Any submission to the IETF intended by the Contributor for publication as all or part of an IETF \
+        Internet-Draft or RFC and any statement made within the context of an IETF activity is considered \
+        an \"IETF Contribution\". Such statements include oral statements in IETF sessions, as well as \
+        written and electronic communications made at any time or place, which are addressed to",
+      "a3fbf07df3fa2fde4f376ca23e82737041605d9f4f4f57bd8cff2c1d4b7955ec2a97948bd3722915c8f3d337f7d37005\
+       0e9e96d647b7c39f56e031ca5eb6250d4042e02785ececfa4b4bb5e8ead0440e20b6e8db09d881a7c6132f420e527950\
+       42bdfa7773d8a9051447b3291ce1411c680465552aa6c405b7764d5e87bea85ad00f8449ed8f72d0d662ab052691ca66\
+       424bc86d2df80ea41f43abf937d3259dc4b2d0dfb48a6c9139ddd7f76966e928e635553ba76c5c879d7b35d49eb2e62b\
+       0871cdac638939e25e8a1e0ef9d5280fa8ca328b351c3c765989cbcf3daa8b6ccc3aaf9f3979c92b3720fc88dc95ed84\
+       a1be059c6499b9fda236e7e818b04b0bc39c1e876b193bfe5569753f88128cc08aaa9b63d1a16f80ef2554d7189c411f\
+       5869ca52c5b83fa36ff216b9c1d30062bebcfd2dc5bce0911934fda79a86f6e698ced759c3ff9b6477338f3da4f9cd85\
+       14ea9982ccafb341b2384dd902f3d1ab7ac61dd29c6f21ba5b862f3730e37cfdc4fd806c22f221",
+  );
+  check_ietf_encrypt(
+      &hex_bytes("1c9240a5eb55d38af333888604f6b5f0473917c1402b80099dca5cbc207075c0"),
+      &hex_bytes("000000000000000000000002"), 42,
+      b"'Twas brillig, and the slithy toves\nDid gyre and gimble in the wabe:\nAll mimsy were the borogoves,\nAnd the mome raths outgrabe.",
+      "62e6347f95ed87a45ffae7426f27a1df5fb69110044c0d73118effa95b01e5cf166d3df2d721caf9b21e5fb14c616871\
+       fd84c54f9d65b283196c7fe4f60553ebf39c6402c42234e32a356b3e764312a61a5532055716ead6962568f87d3f3f77\
+       04c6a8d1bcd1bf4d50d6154b6da731b187b58dfd728afa36757a797ac188d1",
+  );
+}
+
+#[test]
+fn test_chacha20_ietf_counter_overflow() {
+  let mut stream = ChaCha20IetfStream::new(ChaCha20IetfGenerator::from_parts(test_key_buf(), [0; 12], 0));
+  assert!(stream.seek(SeekFrom::End(1)).is_err());
+  assert!(stream.seek(SeekFrom::Start(CHACHA20_IETF_STREAM_LEN + 64)).is_err());
+  assert_eq!(stream.seek(SeekFrom::End(-3)).unwrap(), CHACHA20_IETF_STREAM_LEN - 3);
+  let mut buf = [0; 4];
+  assert_eq!(stream.read(&mut buf).unwrap(), 3);
+  assert_eq!(stream.stream_position().unwrap(), CHACHA20_IETF_STREAM_LEN);
+  assert!(stream.read(&mut buf).is_err());
+  let mut gen = ChaCha20IetfGenerator::from_parts(test_key_buf(), [0; 12], u32::MAX);
+  let mut out = [0; 16];
+  gen.next_gen(&mut out);
+  assert_eq!(gen.stream_position().unwrap(), CHACHA20_IETF_STREAM_LEN);
+  assert!(std::panic::catch_unwind(move || gen.next_gen(&mut out)).is_err());
+}
+
+// draft-irtf-cfrg-xchacha-03, section 2.2.1.
+#[test]
+fn test_hchacha20_draft_2_2_1() {
+  let subkey = hchacha20(test_key_buf(), hex_bytes("000000090000004a0000000031415927"));
+  assert_eq!(&subkey[..], &hex_bytes("82413b4227b27bfed30e42508a877d73a0f9e4d58a74a853c12ec41326d3ecdc")[..]);
+}
+
+// draft-irtf-cfrg-xchacha-03, appendix A.3.2. The published keystream starts
+// at block counter 1.
+#[test]
+fn test_xchacha20_draft_a_3_2() {
+  let key = hex_bytes("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f");
+  let nonce = hex_bytes("404142434445464748494a4b4c4d4e4f5051525354555658");
+  let expected = hex_bytes(
+      "29624b4b1b140ace53740e405b2168540fd7d630c1f536fecd722fc3cddba7f4\
+       cca98cf9e47e5e64d115450f9b125b54449ff76141ca620a1f9cfcab2a1a8a25\
+       5e766a5266b878846120ea64ad99aa479471e63befcbd37cd1c22a221fe46221\
+       5cf32c74895bf505863ccddd48f62916dc6521f1ec50a5ae08903aa259d9bf60\
+       7cd8026fba548604f1b6072d91bc91243a5b845f7fd171b02edc5a0a84cf28dd\
+       241146bc376e3f48df5e7fee1d11048c190a3d3deb0feb64b42d9c6fdeee290f\
+       a0e6ae2c26c0249ea8c181f7e2ffd100cbe5fd3c4f8271d62b15330cb8fdcf00\
+       b3df507ca8c924f7017b7e712d15a2eb5c50484451e54e1b4b995bd8fdd94597\
+       bb94d7af0b2c04df10ba0890899ed9293a0f55b8bafa999264035f1d4fbe7fe0\
+       aafa109a62372027e50e10cdfecca127");
+  let mut out = vec![0; expected.len()];
+  XChaCha20Stream::new(XChaCha20Generator::from_parts(&key, &nonce, 1)).read_exact(&mut out).unwrap();
+  assert_eq!(out, expected);
+  let mut stream = XChaCha20Stream::new(XChaCha20Generator::from_parts(&key, &nonce, 0));
+  assert_eq!(stream.seek(SeekFrom::Start(64 + 100)).unwrap(), 164);
+  stream.read_exact(&mut out[.. 50]).unwrap();
+  assert_eq!(&out[.. 50], &expected[100 .. 150]);
+  assert_eq!(stream.seek_u128(64 + 7).unwrap(), 71);
+  stream.read_exact(&mut out[.. 50]).unwrap();
+  assert_eq!(&out[.. 50], &expected[7 .. 57]);
+}
+
+#[test]
+fn test_chacha_wide_stream_matches_scalar() {
+  let mut wide = ChaCha20WideStream::new(ChaCha20Generator::from_parts(b"expand 32-byte k", TC8_KEY, TC8_NONCE, 0xffff_fff0));
+  let mut scalar = ChaCha20Stream::new(ChaCha20Generator::from_parts(b"expand 32-byte k", TC8_KEY, TC8_NONCE, 0xffff_fff0));
+  let mut expected = [0; 3000];
+  scalar.read_exact(&mut expected).unwrap();
+  let mut out = [0; 3000];
+  wide.read_exact(&mut out[.. 1]).unwrap();
+  wide.read_exact(&mut out[1 .. ]).unwrap();
+  assert_eq!(&out[..], &expected[..]);
+  let mut wide = ChaCha8WideStream::new(ChaCha8Generator::from_parts(b"expand 32-byte k", TC8_KEY, TC8_NONCE, 0));
+  let mut scalar = ChaCha8Stream::new(ChaCha8Generator::from_parts(b"expand 32-byte k", TC8_KEY, TC8_NONCE, 0));
+  scalar.read_exact(&mut expected).unwrap();
+  assert_eq!(wide.seek(SeekFrom::Start(1234)).unwrap(), 1234);
+  wide.read_exact(&mut out[.. 1000]).unwrap();
+  assert_eq!(&out[.. 1000], &expected[1234 .. 2234]);
+  assert_eq!(wide.stream_position().unwrap(), 2234);
+}
+
+#[test]
+fn test_chacha20_ietf_apply_keystream_rfc8439_2_4_2() {
+  let plaintext: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+  let expected = hex_bytes(
+      "6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0bf91b65c5524733ab8f593dabcd62b357\
+       1639d624e65152ab8f530c359f0861d807ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab7793736\
+       5af90bbf74a35be6b40b8eedf2785e42874d");
+  let nonce = hex_bytes("000000000000004a00000000");
+  let mut buf = plaintext.to_vec();
+  let mut stream = ChaCha20IetfStream::new(ChaCha20IetfGenerator::from_parts(test_key_buf(), &nonce, 1));
+  stream.apply_keystream(&mut buf[.. 10]).unwrap();
+  stream.apply_keystream(&mut buf[10 ..]).unwrap();
+  assert_eq!(buf, expected);
+  // Decrypt a suffix starting mid-block by seeking to its offset.
+  let mut stream = ChaCha20IetfStream::new(ChaCha20IetfGenerator::from_parts(test_key_buf(), &nonce, 0));
+  stream.seek(SeekFrom::Start(64 + 70)).unwrap();
+  stream.apply_keystream(&mut buf[70 ..]).unwrap();
+  assert_eq!(&buf[70 ..], &plaintext[70 ..]);
+  stream.seek(SeekFrom::Start(64)).unwrap();
+  stream.apply_keystream(&mut buf[.. 70]).unwrap();
+  assert_eq!(&buf[..], plaintext);
+}
+
+#[test]
+fn test_chacha_debug_redacts_key() {
+  let gen = ChaCha20Generator::from_parts(b"expand 32-byte k", [0xa5; 32], 7, 3);
+  let s = format!("{:?}", gen);
+  assert_eq!(s, "ChaCha20Generator { key: \"<redacted>\", ctr: 3, nonce: 7, end: false }");
+  let gen = XChaCha20Generator::from_parts([0xa5; 32], [0; 24], 0);
+  assert!(format!("{:?}", gen).find("<redacted>").is_some());
+  let gen = ChaCha20IetfGenerator::from_parts([0xa5; 32], [0; 12], 0);
+  let s = format!("{:?}", ChaCha20IetfStream::new(gen));
+  assert!(s.find("<redacted>").is_some());
+  assert!(s.find(&format!("{}", 0xa5a5a5a5_u32)).is_none());
+}
+
+#[test]
+fn test_zeroize_wipes_stream() {
+  use crate::{Zeroize, Zeroizing};
+
+  let key: [u8; 32] = [0xa5; 32];
+  let mut stream = Zeroizing::new(
+      ChaCha20Stream::new(ChaCha20Generator::from_parts(b"expand 32-byte k", key, 1, 0)));
+  let mut buf = [0; 10];
+  stream.read_exact(&mut buf).unwrap();
+  assert!(stream.ubuf.iter().any(|&x| x != 0));
+  assert_eq!(stream.cur, 10);
+  assert!(format!("{:?}", stream).find("a5a5").is_none());
+  stream.zeroize();
+  assert!(stream.ubuf.iter().all(|&x| x == 0));
+  assert_eq!(stream.cur, 0);
+  assert!(stream.gen.state[4 .. 12].iter().all(|&x| x == 0));
+}
 }
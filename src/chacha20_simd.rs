@@ -0,0 +1,184 @@
+use crate::chacha20::{chacha_block};
+
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+#[inline]
+fn chacha_advance(state: &mut [u32; 16], nblocks: u64) {
+  let ctr = ((state[12] as u64) | ((state[13] as u64) << 32)).wrapping_add(nblocks);
+  state[12] = ctr as u32;
+  state[13] = (ctr >> 32) as u32;
+}
+
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+fn chacha_blocks_scalar(double_rounds: usize, state: &[u32; 16], out: &mut [u32]) {
+  let mut x = *state;
+  let mut block = [0; 16];
+  for dst in out.chunks_exact_mut(16) {
+    chacha_block(double_rounds, &x, &mut block);
+    dst.copy_from_slice(&block);
+    chacha_advance(&mut x, 1);
+  }
+}
+
+// The vectorized kernels keep one state word of every block per register,
+// so the rounds are the scalar rounds applied lane-wise, and the blocks are
+// transposed back into the output at the end.
+
+#[cfg(target_arch = "x86_64")]
+macro_rules! chacha_simd_quarter_round {
+  ($x:ident, $add:ident, $xor:ident, $or:ident, $slli:ident, $srli:ident, $a:expr, $b:expr, $c:expr, $d:expr) => {
+    $x[$a] = $add($x[$a], $x[$b]); $x[$d] = $xor($x[$d], $x[$a]); $x[$d] = $or($slli($x[$d], 16), $srli($x[$d], 16));
+    $x[$c] = $add($x[$c], $x[$d]); $x[$b] = $xor($x[$b], $x[$c]); $x[$b] = $or($slli($x[$b], 12), $srli($x[$b], 20));
+    $x[$a] = $add($x[$a], $x[$b]); $x[$d] = $xor($x[$d], $x[$a]); $x[$d] = $or($slli($x[$d], 8), $srli($x[$d], 24));
+    $x[$c] = $add($x[$c], $x[$d]); $x[$b] = $xor($x[$b], $x[$c]); $x[$b] = $or($slli($x[$b], 7), $srli($x[$b], 25));
+  };
+}
+
+#[cfg(target_arch = "x86_64")]
+macro_rules! chacha_simd_blocks {
+  ($name:ident, $feature:tt, $lanes:expr, $vec:ident, $set1:ident, $loadu:ident, $storeu:ident,
+   $add:ident, $xor:ident, $or:ident, $slli:ident, $srli:ident) => {
+    #[target_feature(enable = $feature)]
+    unsafe fn $name(double_rounds: usize, state: &[u32; 16], out: &mut [u32]) {
+      assert_eq!(out.len(), 16 * $lanes);
+      let ctr = (state[12] as u64) | ((state[13] as u64) << 32);
+      let mut ctr_lo = [0_u32; $lanes];
+      let mut ctr_hi = [0_u32; $lanes];
+      for b in 0 .. $lanes {
+        let c = ctr.wrapping_add(b as u64);
+        ctr_lo[b] = c as u32;
+        ctr_hi[b] = (c >> 32) as u32;
+      }
+      let mut init = [$set1(0); 16];
+      for k in 0 .. 16 {
+        init[k] = $set1(state[k] as i32);
+      }
+      init[12] = $loadu(ctr_lo.as_ptr() as *const $vec);
+      init[13] = $loadu(ctr_hi.as_ptr() as *const $vec);
+      let mut x = init;
+      for _ in 0 .. double_rounds {
+        chacha_simd_quarter_round!(x, $add, $xor, $or, $slli, $srli, 0, 4, 8, 12);
+        chacha_simd_quarter_round!(x, $add, $xor, $or, $slli, $srli, 1, 5, 9, 13);
+        chacha_simd_quarter_round!(x, $add, $xor, $or, $slli, $srli, 2, 6, 10, 14);
+        chacha_simd_quarter_round!(x, $add, $xor, $or, $slli, $srli, 3, 7, 11, 15);
+        chacha_simd_quarter_round!(x, $add, $xor, $or, $slli, $srli, 0, 5, 10, 15);
+        chacha_simd_quarter_round!(x, $add, $xor, $or, $slli, $srli, 1, 6, 11, 12);
+        chacha_simd_quarter_round!(x, $add, $xor, $or, $slli, $srli, 2, 7, 8, 13);
+        chacha_simd_quarter_round!(x, $add, $xor, $or, $slli, $srli, 3, 4, 9, 14);
+      }
+      let mut lanes = [0_u32; $lanes];
+      for k in 0 .. 16 {
+        $storeu(lanes.as_mut_ptr() as *mut $vec, $add(x[k], init[k]));
+        for b in 0 .. $lanes {
+          out[b * 16 + k] = lanes[b];
+        }
+      }
+    }
+  };
+}
+
+#[cfg(target_arch = "x86_64")]
+chacha_simd_blocks!(chacha_blocks4_sse2, "sse2", 4, __m128i, _mm_set1_epi32, _mm_loadu_si128, _mm_storeu_si128,
+    _mm_add_epi32, _mm_xor_si128, _mm_or_si128, _mm_slli_epi32, _mm_srli_epi32);
+
+#[cfg(target_arch = "x86_64")]
+chacha_simd_blocks!(chacha_blocks8_avx2, "avx2", 8, __m256i, _mm256_set1_epi32, _mm256_loadu_si256, _mm256_storeu_si256,
+    _mm256_add_epi32, _mm256_xor_si256, _mm256_or_si256, _mm256_slli_epi32, _mm256_srli_epi32);
+
+// The 8-block fallback for CPUs without AVX2: two 4-block SSE2 passes.
+#[cfg(target_arch = "x86_64")]
+fn chacha_blocks8_sse2(double_rounds: usize, state: &[u32; 16], out: &mut [u32]) {
+  assert_eq!(out.len(), 128);
+  let mut x = *state;
+  unsafe { chacha_blocks4_sse2(double_rounds, &x, &mut out[ .. 64]) };
+  chacha_advance(&mut x, 4);
+  unsafe { chacha_blocks4_sse2(double_rounds, &x, &mut out[64 .. ]) };
+}
+
+/// Computes 4 consecutive blocks and advances the counter by 4.
+pub fn chacha_next4(double_rounds: usize, state: &mut [u32; 16], out: &mut [u32; 64]) {
+  #[cfg(target_arch = "x86_64")]
+  unsafe { chacha_blocks4_sse2(double_rounds, state, out) };
+  #[cfg(not(target_arch = "x86_64"))]
+  chacha_blocks_scalar(double_rounds, state, out);
+  chacha_advance(state, 4);
+}
+
+/// Computes 8 consecutive blocks and advances the counter by 8, using AVX2
+/// when the CPU supports it.
+pub fn chacha_next8(double_rounds: usize, state: &mut [u32; 16], out: &mut [u32; 128]) {
+  #[cfg(target_arch = "x86_64")]
+  {
+    if is_x86_feature_detected!("avx2") {
+      unsafe { chacha_blocks8_avx2(double_rounds, state, out) };
+    } else {
+      chacha_blocks8_sse2(double_rounds, state, out);
+    }
+  }
+  #[cfg(not(target_arch = "x86_64"))]
+  chacha_blocks_scalar(double_rounds, state, out);
+  chacha_advance(state, 8);
+}
+
+#[cfg(test)]
+mod tests {
+use super::{chacha_next4, chacha_next8, chacha_blocks_scalar};
+
+fn test_state(ctr: u64) -> [u32; 16] {
+  let mut state = [0; 16];
+  for (k, x) in state.iter_mut().enumerate() {
+    *x = (k as u32).wrapping_mul(0x9e3779b9) ^ 0x0123_4567;
+  }
+  state[12] = ctr as u32;
+  state[13] = (ctr >> 32) as u32;
+  state
+}
+
+#[test]
+fn test_chacha_next4_matches_scalar() {
+  for &double_rounds in [4, 6, 10].iter() {
+    for &ctr in [0, 0xffff_fffe, u64::MAX - 1].iter() {
+      let mut state = test_state(ctr);
+      let mut expected = [0; 64];
+      chacha_blocks_scalar(double_rounds, &state, &mut expected);
+      let mut out = [0; 64];
+      chacha_next4(double_rounds, &mut state, &mut out);
+      assert_eq!(&out[..], &expected[..]);
+      assert_eq!(state, test_state(ctr.wrapping_add(4)));
+    }
+  }
+}
+
+#[test]
+fn test_chacha_next8_matches_scalar() {
+  for &double_rounds in [4, 6, 10].iter() {
+    for &ctr in [0, 0xffff_fffc, u64::MAX - 3].iter() {
+      let mut state = test_state(ctr);
+      let mut expected = [0; 128];
+      chacha_blocks_scalar(double_rounds, &state, &mut expected);
+      let mut out = [0; 128];
+      chacha_next8(double_rounds, &mut state, &mut out);
+      assert_eq!(&out[..], &expected[..]);
+      assert_eq!(state, test_state(ctr.wrapping_add(8)));
+    }
+  }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[test]
+fn test_chacha_blocks8_sse2_matches_scalar() {
+  use super::{chacha_blocks8_sse2};
+
+  for &double_rounds in [4, 6, 10].iter() {
+    for &ctr in [0, 0xffff_fffc, u64::MAX - 3].iter() {
+      let state = test_state(ctr);
+      let mut expected = [0; 128];
+      chacha_blocks_scalar(double_rounds, &state, &mut expected);
+      let mut out = [0; 128];
+      chacha_blocks8_sse2(double_rounds, &state, &mut out);
+      assert_eq!(&out[..], &expected[..]);
+    }
+  }
+}
+}
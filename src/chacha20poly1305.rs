@@ -0,0 +1,179 @@
+use crate::{Zeroizing, zero_volatile};
+use crate::chacha20::{ChaCha20IetfGenerator, ChaCha20IetfStream};
+use crate::poly1305::{Poly1305State, POLY1305_KEY_LEN, POLY1305_TAG_LEN, tag_eq};
+
+use std::io::{Read, Error as IoError, ErrorKind as IoErrorKind};
+
+pub const CHACHA20POLY1305_KEY_LEN: usize = 32;
+pub const CHACHA20POLY1305_NONCE_LEN: usize = 12;
+pub const CHACHA20POLY1305_TAG_LEN: usize = POLY1305_TAG_LEN;
+
+/// ChaCha20-Poly1305 AEAD (RFC 8439, section 2.8). A nonce must never be
+/// reused with the same key.
+pub struct ChaCha20Poly1305 {
+  key:  [u8; CHACHA20POLY1305_KEY_LEN],
+}
+
+impl Drop for ChaCha20Poly1305 {
+  fn drop(&mut self) {
+    zero_volatile(&mut self.key);
+  }
+}
+
+impl ChaCha20Poly1305 {
+  pub fn new<K: AsRef<[u8]>>(key_buf: K) -> ChaCha20Poly1305 {
+    let key_buf = key_buf.as_ref();
+    assert_eq!(key_buf.len(), CHACHA20POLY1305_KEY_LEN);
+    let mut aead = ChaCha20Poly1305{key: [0; CHACHA20POLY1305_KEY_LEN]};
+    aead.key.copy_from_slice(key_buf);
+    aead
+  }
+
+  fn stream(&self, nonce: &[u8]) -> (Zeroizing<ChaCha20IetfStream>, Poly1305State) {
+    assert_eq!(nonce.len(), CHACHA20POLY1305_NONCE_LEN);
+    let mut stream = Zeroizing::new(ChaCha20IetfStream::new(ChaCha20IetfGenerator::from_parts(&self.key[..], nonce, 0)));
+    let mut otk = [0; 64];
+    stream.read_exact(&mut otk).unwrap();
+    let mac = Poly1305State::new(&otk[ .. POLY1305_KEY_LEN]);
+    zero_volatile(&mut otk);
+    (stream, mac)
+  }
+
+  fn tag(mut mac: Poly1305State, aad: &[u8], ciphertext: &[u8]) -> [u8; CHACHA20POLY1305_TAG_LEN] {
+    mac.update(aad);
+    mac.pad16();
+    mac.update(ciphertext);
+    mac.pad16();
+    mac.update(&(aad.len() as u64).to_le_bytes());
+    mac.update(&(ciphertext.len() as u64).to_le_bytes());
+    mac.finalize()
+  }
+
+  /// Encrypts `buf` in place and returns the detached tag.
+  pub fn seal_in_place(&self, nonce: &[u8], aad: &[u8], buf: &mut [u8]) -> Result<[u8; CHACHA20POLY1305_TAG_LEN], IoError> {
+    let (mut stream, mac) = self.stream(nonce);
+    stream.apply_keystream(buf)?;
+    Ok(ChaCha20Poly1305::tag(mac, aad, buf))
+  }
+
+  /// Verifies the detached tag and, only if it matches, decrypts `buf` in
+  /// place.
+  pub fn open_in_place(&self, nonce: &[u8], aad: &[u8], buf: &mut [u8], tag: &[u8]) -> Result<(), IoError> {
+    let (mut stream, mac) = self.stream(nonce);
+    let expected = ChaCha20Poly1305::tag(mac, aad, buf);
+    if !tag_eq(&expected, tag) {
+      return Err(IoError::new(IoErrorKind::InvalidData, "ChaCha20-Poly1305 authentication failed"));
+    }
+    stream.apply_keystream(buf)
+  }
+
+  /// Returns the ciphertext with the tag appended.
+  pub fn seal(&self, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, IoError> {
+    let mut buf = plaintext.to_vec();
+    let tag = self.seal_in_place(nonce, aad, &mut buf)?;
+    buf.extend_from_slice(&tag);
+    Ok(buf)
+  }
+
+  /// Opens a ciphertext with the tag appended, as produced by `seal`.
+  pub fn open(&self, nonce: &[u8], aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>, IoError> {
+    if sealed.len() < CHACHA20POLY1305_TAG_LEN {
+      return Err(IoError::new(IoErrorKind::InvalidData, "ChaCha20-Poly1305 ciphertext is shorter than the tag"));
+    }
+    let (ciphertext, tag) = sealed.split_at(sealed.len() - CHACHA20POLY1305_TAG_LEN);
+    let mut buf = ciphertext.to_vec();
+    self.open_in_place(nonce, aad, &mut buf, tag)?;
+    Ok(buf)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+use super::{ChaCha20Poly1305};
+use crate::{hex_bytes};
+use crate::chacha20::{ChaCha20IetfGenerator, ChaCha20IetfStream};
+
+use std::io::{Read};
+
+const SUNSCREEN: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+fn check_poly1305_key_gen(key_hex: &str, nonce_hex: &str, otk_hex: &str) {
+  let mut otk = [0; 32];
+  ChaCha20IetfStream::new(ChaCha20IetfGenerator::from_parts(hex_bytes(key_hex), hex_bytes(nonce_hex), 0)).read_exact(&mut otk).unwrap();
+  assert_eq!(&otk[..], &hex_bytes(otk_hex)[..]);
+}
+
+// RFC 8439, section 2.6.2 and appendix A.4.
+#[test]
+fn test_poly1305_key_gen_rfc8439() {
+  check_poly1305_key_gen(
+      "808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f", "000000000001020304050607",
+      "8ad5a08b905f81cc815040274ab29471a833b637e3fd0da508dbb8e2fdd1a646");
+  check_poly1305_key_gen(
+      "0000000000000000000000000000000000000000000000000000000000000000", "000000000000000000000000",
+      "76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7");
+  check_poly1305_key_gen(
+      "0000000000000000000000000000000000000000000000000000000000000001", "000000000000000000000002",
+      "ecfa254f845f647473d3cb140da9e87606cb33066c447b87bc2666dde3fbb739");
+  check_poly1305_key_gen(
+      "1c9240a5eb55d38af333888604f6b5f0473917c1402b80099dca5cbc207075c0", "000000000000000000000002",
+      "965e3bc6f9ec7ed9560808f4d229f94b137ff275ca9b3fcbdd59deaad23310ae");
+}
+
+// RFC 8439, section 2.8.2.
+#[test]
+fn test_chacha20poly1305_rfc8439_2_8_2() {
+  let aead = ChaCha20Poly1305::new(hex_bytes("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f"));
+  let nonce = hex_bytes("070000004041424344454647");
+  let aad = hex_bytes("50515253c0c1c2c3c4c5c6c7");
+  let expected_ciphertext = hex_bytes(
+      "d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d63dbea45e8ca9671282fafb69da92728b\
+       1a71de0a9e060b2905d6a5b67ecd3b3692ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc\
+       3ff4def08e4b7a9de576d26586cec64b6116");
+  let expected_tag = hex_bytes("1ae10b594f09e26a7e902ecbd0600691");
+  let mut buf = SUNSCREEN.to_vec();
+  let tag = aead.seal_in_place(&nonce, &aad, &mut buf).unwrap();
+  assert_eq!(buf, expected_ciphertext);
+  assert_eq!(&tag[..], &expected_tag[..]);
+  aead.open_in_place(&nonce, &aad, &mut buf, &tag).unwrap();
+  assert_eq!(&buf[..], SUNSCREEN);
+}
+
+// RFC 8439, appendix A.5.
+#[test]
+fn test_chacha20poly1305_rfc8439_a_5() {
+  let aead = ChaCha20Poly1305::new(hex_bytes("1c9240a5eb55d38af333888604f6b5f0473917c1402b80099dca5cbc207075c0"));
+  let nonce = hex_bytes("000000000102030405060708");
+  let aad = hex_bytes("f33388860000000000004e91");
+  let mut sealed = hex_bytes(
+      "64a0861575861af460f062c79be643bd5e805cfd345cf389f108670ac76c8cb24c6cfc18755d43eea09ee94e382d26b0\
+       bdb7b73c321b0100d4f03b7f355894cf332f830e710b97ce98c8a84abd0b948114ad176e008d33bd60f982b1ff37c855\
+       9797a06ef4f0ef61c186324e2b3506383606907b6a7c02b0f9f6157b53c867e4b9166c767b804d46a59b5216cde7a4e9\
+       9040c5a40433225ee282a1b0a06c523eaf4534d7f83fa1155b0047718cbc546a0d072b04b3564eea1b422273f548271a\
+       0bb2316053fa76991955ebd63159434ecebb4e466dae5a1073a6727627097a1049e617d91d361094fa68f0ff77987130\
+       305beaba2eda04df997b714d6c6f2c29a6ad5cb4022b02709b");
+  sealed.extend_from_slice(&hex_bytes("eead9d67890cbb22392336fea1851f38"));
+  let plaintext = aead.open(&nonce, &aad, &sealed).unwrap();
+  assert_eq!(&plaintext[..], &b"Internet-Drafts are draft documents valid for a maximum of six months and may be \
+      updated, replaced, or obsoleted by other documents at any time. It is inappropriate to use Internet-Drafts as \
+      reference material or to cite them other than as /\xe2\x80\x9cwork in progress./\xe2\x80\x9d"[..]);
+}
+
+#[test]
+fn test_chacha20poly1305_open_rejects_forgery() {
+  let aead = ChaCha20Poly1305::new([7; 32]);
+  let nonce = [1; 12];
+  let mut sealed = aead.seal(&nonce, b"header", SUNSCREEN).unwrap();
+  assert_eq!(sealed.len(), SUNSCREEN.len() + 16);
+  assert_eq!(&aead.open(&nonce, b"header", &sealed).unwrap()[..], SUNSCREEN);
+  assert!(aead.open(&nonce, b"headers", &sealed).is_err());
+  assert!(aead.open(&[2; 12], b"header", &sealed).is_err());
+  assert!(aead.open(&nonce, b"header", &sealed[.. 10]).is_err());
+  sealed[3] ^= 1;
+  assert!(aead.open(&nonce, b"header", &sealed).is_err());
+  sealed[3] ^= 1;
+  let n = sealed.len();
+  sealed[n - 1] ^= 0x80;
+  assert!(aead.open(&nonce, b"header", &sealed).is_err());
+}
+}
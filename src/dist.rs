@@ -1,24 +1,107 @@
-use byteorder::{ReadBytesExt, LittleEndian as LE};
+use crate::{Stream};
 
-use std::io::{Read};
+#[cfg(feature = "serde")]
+use crate::snapshot::{invalid_snapshot};
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "serde")]
+use std::convert::{TryFrom};
+#[cfg(feature = "serde")]
+use std::io::{Error as IoError};
 use std::ops::{RangeBounds, Bound, Range, RangeInclusive, RangeTo, RangeToInclusive};
 
 pub trait DrawNew {
-  fn draw_new<Rng: Read>(rng: Rng) -> Self where Self: Sized;
+  fn draw_new<Rng: Stream>(rng: Rng) -> Self where Self: Sized;
+}
+
+macro_rules! draw_new_int {
+  ($ty:tt, $next:ident) => {
+    impl DrawNew for $ty {
+      #[inline]
+      fn draw_new<Rng: Stream>(mut rng: Rng) -> $ty {
+        rng.$next() as $ty
+      }
+    }
+  };
+}
+
+draw_new_int!(u8, next_u8);
+draw_new_int!(u16, next_u16);
+draw_new_int!(u32, next_u32);
+draw_new_int!(u64, next_u64);
+draw_new_int!(i8, next_u8);
+draw_new_int!(i16, next_u16);
+draw_new_int!(i32, next_u32);
+draw_new_int!(i64, next_u64);
+
+impl DrawNew for bool {
+  #[inline]
+  fn draw_new<Rng: Stream>(mut rng: Rng) -> bool {
+    (rng.next_u8() & 1) != 0
+  }
+}
+
+/// Uniform on `[0, 1)`, using the upper 24 bits of a `u32`.
+impl DrawNew for f32 {
+  #[inline]
+  fn draw_new<Rng: Stream>(mut rng: Rng) -> f32 {
+    (rng.next_u32() >> 8) as f32 * (1.0 / (1_u32 << 24) as f32)
+  }
+}
+
+/// Uniform on `[0, 1)`, using the upper 53 bits of a `u64`.
+impl DrawNew for f64 {
+  #[inline]
+  fn draw_new<Rng: Stream>(mut rng: Rng) -> f64 {
+    (rng.next_u64() >> 11) as f64 * (1.0 / (1_u64 << 53) as f64)
+  }
 }
 
 pub trait Draw {
   type Item;
 
-  fn draw<Rng: Read>(self, rng: Rng) -> Self::Item;
+  fn draw<Rng: Stream>(self, rng: Rng) -> Self::Item;
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "FastRangeU32State"))]
 pub struct FastRangeU32 {
   ub:   u32,
   cut:  u32,
 }
 
+/// Unchecked form of `FastRangeU32`, for deserialization.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "FastRangeU32")]
+struct FastRangeU32State {
+  ub:   u32,
+  cut:  u32,
+}
+
+/// Accepts only the states `new`, `draw` and `clear` can produce; any other
+/// rejection threshold would bias or stall the rejection loop.
+#[cfg(feature = "serde")]
+impl TryFrom<FastRangeU32State> for FastRangeU32 {
+  type Error = IoError;
+
+  fn try_from(raw: FastRangeU32State) -> Result<FastRangeU32, IoError> {
+    let FastRangeU32State{ub, cut} = raw;
+    let valid = if ub == 0 {
+      cut == 0
+    } else {
+      cut == ub || cut == ub.wrapping_neg() % ub
+    };
+    if !valid {
+      return Err(invalid_snapshot(format!("FastRangeU32 cut {} is invalid for bound {}", cut, ub)));
+    }
+    Ok(FastRangeU32{ub, cut})
+  }
+}
+
 impl Default for FastRangeU32 {
   #[inline]
   fn default() -> FastRangeU32 {
@@ -57,12 +140,12 @@ impl FastRangeU32 {
 impl<'a> Draw for &'a mut FastRangeU32 {
   type Item = u32;
 
-  fn draw<R: Read>(self, mut rng: R) -> u32 {
+  fn draw<R: Stream>(self, mut rng: R) -> u32 {
     let s = self.ub;
     if s == 1 {
       return 0;
     }
-    let mut x = rng.read_u32::<LE>().unwrap();
+    let mut x = rng.next_u32();
     let mut m = (x as u64).wrapping_mul(s as u64);
     let mut k = m as u32;
     if k < s {
@@ -74,7 +157,7 @@ impl<'a> Draw for &'a mut FastRangeU32 {
         self.cut
       };
       while k < t {
-        x = rng.read_u32::<LE>().unwrap();
+        x = rng.next_u32();
         m = (x as u64).wrapping_mul(s as u64);
         k = m as u32;
       }
@@ -86,7 +169,7 @@ impl<'a> Draw for &'a mut FastRangeU32 {
 /*impl<R: RangeBounds<u32>> Draw for R {
   type Item = u32;
 
-  fn draw<Rng: Read>(self, rng: Rng) -> u32 {
+  fn draw<Rng: Stream>(self, rng: Rng) -> u32 {
     draw_range_u32(self, rng)
   }
 }*/
@@ -96,7 +179,7 @@ macro_rules! draw_range {
     impl Draw for $rg<$ty> {
       type Item = $ty;
 
-      fn draw<Rng: Read>(self, rng: Rng) -> $ty {
+      fn draw<Rng: Stream>(self, rng: Rng) -> $ty {
         let lb = match self.start_bound() {
           Bound::Included(&lb) => lb,
           Bound::Excluded(&lb) => lb + 1,
@@ -131,7 +214,7 @@ draw_range!(RangeInclusive, u32);
 draw_range!(RangeTo, u32);
 draw_range!(RangeToInclusive, u32);
 
-/*pub fn draw_range_u8<R: RangeBounds<u8>, Rng: Read>(r: R, rng: Rng) -> u8 {
+/*pub fn draw_range_u8<R: RangeBounds<u8>, Rng: Stream>(r: R, rng: Rng) -> u8 {
   let lb = match r.start_bound() {
     Bound::Included(&lb) => lb,
     Bound::Excluded(&lb) => lb + 1,
@@ -145,7 +228,7 @@ draw_range!(RangeToInclusive, u32);
   lb + (FastRangeU32::new((ub - lb) as _).draw(rng) as u8)
 }
 
-pub fn draw_range_u32<R: RangeBounds<u32>, Rng: Read>(r: R, rng: Rng) -> u32 {
+pub fn draw_range_u32<R: RangeBounds<u32>, Rng: Stream>(r: R, rng: Rng) -> u32 {
   let lb = match r.start_bound() {
     Bound::Included(&lb) => lb,
     Bound::Excluded(&lb) => lb + 1,
@@ -162,19 +245,119 @@ pub fn draw_range_u32<R: RangeBounds<u32>, Rng: Read>(r: R, rng: Rng) -> u32 {
 impl<'a, T: Copy> Draw for &'a [T] {
   type Item = T;
 
-  fn draw<Rng: Read>(self, rng: Rng) -> T {
+  fn draw<Rng: Stream>(self, rng: Rng) -> T {
     let mut r = FastRangeU32::new(self.len() as _);
     let i = r.draw(rng);
     self[i as usize]
   }
 }
 
-pub fn shuffle<S: AsMut<[T]>, T, R: Read>(mut buf: S, mut rng: R) {
+/// Walker's alias method (in Vose's formulation) for drawing from a
+/// categorical distribution in constant time.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "AliasSampler32State"))]
+pub struct AliasSampler32 {
+  pr_alias: Vec<(f32, u32)>,
+}
+
+/// Unchecked form of `AliasSampler32`, for deserialization.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "AliasSampler32")]
+struct AliasSampler32State {
+  pr_alias: Vec<(f32, u32)>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<AliasSampler32State> for AliasSampler32 {
+  type Error = IoError;
+
+  fn try_from(raw: AliasSampler32State) -> Result<AliasSampler32, IoError> {
+    let pr_alias = raw.pr_alias;
+    if pr_alias.is_empty() {
+      return Err(invalid_snapshot("AliasSampler32 has no categories"));
+    }
+    if pr_alias.len() > u32::MAX as usize {
+      return Err(invalid_snapshot("AliasSampler32 has too many categories"));
+    }
+    for &(p, a) in pr_alias.iter() {
+      if !(0.0 ..= 1.0).contains(&p) {
+        return Err(invalid_snapshot(format!("AliasSampler32 probability {} is out of range", p)));
+      }
+      if a as usize >= pr_alias.len() {
+        return Err(invalid_snapshot(format!("AliasSampler32 alias {} is out of range", a)));
+      }
+    }
+    Ok(AliasSampler32{pr_alias})
+  }
+}
+
+impl AliasSampler32 {
+  /// `probs` should sum to 1.
+  pub fn new(probs: &[f32]) -> AliasSampler32 {
+    assert!(!probs.is_empty());
+    assert!(probs.len() <= u32::MAX as usize);
+    let n = probs.len() as f32;
+    let mut pr_alias = vec![(0.0, u32::MAX); probs.len()];
+    let mut small = Vec::with_capacity(probs.len());
+    let mut large = Vec::with_capacity(probs.len());
+    for (k, &p) in probs.iter().enumerate() {
+      let u = p * n;
+      if u < 1.0 {
+        small.push((u, k as u32));
+      } else {
+        large.push((u, k as u32));
+      }
+    }
+    while !small.is_empty() && !large.is_empty() {
+      let (s_u, s_k) = small.pop().unwrap();
+      let (l_u, l_k) = large.pop().unwrap();
+      pr_alias[s_k as usize] = (s_u, l_k);
+      let new_l_u = (s_u + l_u) - 1.0;
+      if new_l_u < 1.0 {
+        small.push((new_l_u, l_k));
+      } else {
+        large.push((new_l_u, l_k));
+      }
+    }
+    // Whatever remains has probability 1 up to rounding.
+    for (_, k) in large.into_iter().chain(small) {
+      pr_alias[k as usize] = (1.0, k);
+    }
+    AliasSampler32{pr_alias}
+  }
+
+  pub fn len(&self) -> usize {
+    self.pr_alias.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.pr_alias.is_empty()
+  }
+}
+
+impl Draw for &AliasSampler32 {
+  type Item = u32;
+
+  fn draw<Rng: Stream>(self, mut rng: Rng) -> u32 {
+    let k = FastRangeU32::new(self.pr_alias.len() as u32).draw(&mut rng);
+    let x = f32::draw_new(&mut rng);
+    let (p_k, a_k) = self.pr_alias[k as usize];
+    if x < p_k {
+      k
+    } else {
+      a_k
+    }
+  }
+}
+
+pub fn shuffle<S: AsMut<[T]>, T, R: Stream>(mut buf: S, mut rng: R) {
   let buf = buf.as_mut();
   if buf.len() <= 1 {
     return;
   }
-  assert!(buf.len() <= u32::max_value() as usize);
+  assert!(buf.len() <= u32::MAX as usize);
   let len = buf.len() as u32;
   let mut r = FastRangeU32::default();
   for off in 0 .. len - 1 {
@@ -185,3 +368,85 @@ pub fn shuffle<S: AsMut<[T]>, T, R: Read>(mut buf: S, mut rng: R) {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+use super::{AliasSampler32, Draw};
+use crate::splitmix::{Splitmix64Generator};
+
+#[test]
+fn test_alias_sampler() {
+  let probs = [0.5, 0.0, 0.125, 0.375];
+  let alias = AliasSampler32::new(&probs);
+  let mut rng = Splitmix64Generator::from(1);
+  let mut counts = [0; 4];
+  for _ in 0 .. 80000 {
+    counts[alias.draw(&mut rng) as usize] += 1;
+  }
+  assert_eq!(counts[1], 0);
+  for k in 0 .. 4 {
+    let p = counts[k] as f32 / 80000.0;
+    assert!((p - probs[k]).abs() < 0.01, "k={} p={}", k, p);
+  }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_dist_serde_tokens() {
+  use super::{FastRangeU32};
+  use serde_test::{Token, assert_tokens};
+
+  assert_tokens(&FastRangeU32::new(10), &[
+      Token::Struct{name: "FastRangeU32", len: 2},
+      Token::Str("ub"), Token::U32(10),
+      Token::Str("cut"), Token::U32(10),
+      Token::StructEnd,
+  ]);
+  let alias = AliasSampler32::new(&[0.25, 0.75]);
+  assert_tokens(&alias, &[
+      Token::Struct{name: "AliasSampler32", len: 1},
+      Token::Str("pr_alias"),
+      Token::Seq{len: Some(2)},
+      Token::Tuple{len: 2}, Token::F32(0.5), Token::U32(1), Token::TupleEnd,
+      Token::Tuple{len: 2}, Token::F32(1.0), Token::U32(1), Token::TupleEnd,
+      Token::SeqEnd,
+      Token::StructEnd,
+  ]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_dist_serde_rejects_invalid_states() {
+  use super::{FastRangeU32};
+  use serde_test::{Token, assert_de_tokens, assert_de_tokens_error};
+
+  let range = |ub: u32, cut: u32| vec![
+      Token::Struct{name: "FastRangeU32", len: 2},
+      Token::Str("ub"), Token::U32(ub),
+      Token::Str("cut"), Token::U32(cut),
+      Token::StructEnd,
+  ];
+  assert_de_tokens(&FastRangeU32::default(), &range(0, 0));
+  assert_de_tokens(&FastRangeU32::new(5), &range(5, 5));
+  assert_de_tokens(&FastRangeU32{ub: 5, cut: 1}, &range(5, 1));
+  assert_de_tokens_error::<FastRangeU32>(&range(5, u32::MAX), "FastRangeU32 cut 4294967295 is invalid for bound 5");
+  assert_de_tokens_error::<FastRangeU32>(&range(0, 3), "FastRangeU32 cut 3 is invalid for bound 0");
+  let alias = |pr_alias: &[(f32, u32)]| {
+    let mut tokens = vec![
+        Token::Struct{name: "AliasSampler32", len: 1},
+        Token::Str("pr_alias"),
+        Token::Seq{len: Some(pr_alias.len())},
+    ];
+    for &(p, a) in pr_alias.iter() {
+      tokens.extend(vec![Token::Tuple{len: 2}, Token::F32(p), Token::U32(a), Token::TupleEnd]);
+    }
+    tokens.extend(vec![Token::SeqEnd, Token::StructEnd]);
+    tokens
+  };
+  assert_de_tokens_error::<AliasSampler32>(&alias(&[]), "AliasSampler32 has no categories");
+  assert_de_tokens_error::<AliasSampler32>(&alias(&[(0.5, 1), (1.0, 2)]), "AliasSampler32 alias 2 is out of range");
+  assert_de_tokens_error::<AliasSampler32>(&alias(&[(f32::NAN, 0)]), "AliasSampler32 probability NaN is out of range");
+  assert_de_tokens_error::<AliasSampler32>(&alias(&[(1.5, 0)]), "AliasSampler32 probability 1.5 is out of range");
+  assert_de_tokens_error::<AliasSampler32>(&alias(&[(-0.25, 0)]), "AliasSampler32 probability -0.25 is out of range");
+}
+}
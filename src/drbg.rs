@@ -0,0 +1,202 @@
+use crate::{Stream, zero_volatile};
+use crate::chacha20_simd::{chacha_next8};
+use crate::os::{RandomStream, fork_epoch};
+
+use byteorder::{ByteOrder, LittleEndian as LE};
+
+use std::io::{Read, Error as IoError};
+
+const KEY_LEN: usize = 32;
+const BUF_LEN: usize = 512;
+
+/// ChaCha20 DRBG with forward secrecy via "fast key erasure"
+/// (<https://blog.cr.yp.to/20170723-random.html>).
+///
+/// Each refill expands the current key into 8 blocks of keystream; the first
+/// 32 bytes immediately replace the key, and the remaining bytes are handed
+/// out and zeroed as they are consumed. A later compromise of the state thus
+/// reveals neither past output nor past keys.
+///
+/// A DRBG created by `from_os` reseeds itself in the child after a `fork`.
+pub struct ChaChaDrbg {
+  key:  [u8; KEY_LEN],
+  buf:  [u8; BUF_LEN],
+  cur:  usize,
+  reseed_interval:  Option<u64>,
+  since_reseed:     u64,
+  fork_epoch:       Option<usize>,
+}
+
+impl Drop for ChaChaDrbg {
+  fn drop(&mut self) {
+    zero_volatile(&mut self.key);
+    zero_volatile(&mut self.buf);
+    self.cur = BUF_LEN;
+  }
+}
+
+impl ChaChaDrbg {
+  pub fn new<K: AsRef<[u8]>>(key_buf: K) -> ChaChaDrbg {
+    let key_buf = key_buf.as_ref();
+    assert_eq!(key_buf.len(), KEY_LEN);
+    let mut key = [0; KEY_LEN];
+    key.copy_from_slice(key_buf);
+    ChaChaDrbg{
+      key,
+      buf:  [0; BUF_LEN],
+      cur:  BUF_LEN,
+      reseed_interval:  None,
+      since_reseed:     0,
+      fork_epoch:       None,
+    }
+  }
+
+  pub fn from_os() -> Result<ChaChaDrbg, IoError> {
+    let mut key = [0; KEY_LEN];
+    RandomStream::default().read_exact(&mut key)?;
+    let mut drbg = ChaChaDrbg::new(&key[..]);
+    drbg.fork_epoch = Some(fork_epoch());
+    zero_volatile(&mut key);
+    Ok(drbg)
+  }
+
+  /// Reseeds from `os::RandomStream` automatically once this many bytes
+  /// have been drawn since the previous reseed.
+  pub fn set_reseed_interval(&mut self, interval: Option<u64>) {
+    self.reseed_interval = interval;
+  }
+
+  /// Mixes fresh OS entropy into the key and discards any buffered output.
+  pub fn reseed(&mut self) -> Result<(), IoError> {
+    let mut entropy = [0; KEY_LEN];
+    RandomStream::default().read_exact(&mut entropy)?;
+    self.reseed_from(&entropy);
+    zero_volatile(&mut entropy);
+    Ok(())
+  }
+
+  pub fn reseed_from(&mut self, entropy: &[u8]) {
+    assert_eq!(entropy.len(), KEY_LEN);
+    for (k, &e) in self.key.iter_mut().zip(entropy.iter()) {
+      *k ^= e;
+    }
+    zero_volatile(&mut self.buf[self.cur .. ]);
+    self.cur = BUF_LEN;
+    self.since_reseed = 0;
+    if self.fork_epoch.is_some() {
+      self.fork_epoch = Some(fork_epoch());
+    }
+  }
+
+  fn refill(&mut self) {
+    let mut state = [0; 16];
+    LE::read_u32_into(b"expand 32-byte k", &mut state[0 .. 4]);
+    LE::read_u32_into(&self.key, &mut state[4 .. 12]);
+    let mut blocks = [0; 128];
+    chacha_next8(10, &mut state, &mut blocks);
+    LE::write_u32_into(&blocks, &mut self.buf);
+    zero_volatile(&mut state);
+    zero_volatile(&mut blocks);
+    self.key.copy_from_slice(&self.buf[ .. KEY_LEN]);
+    zero_volatile(&mut self.buf[ .. KEY_LEN]);
+    self.cur = KEY_LEN;
+  }
+
+  fn fill(&mut self, out: &mut [u8]) -> Result<(), IoError> {
+    if let Some(epoch) = self.fork_epoch {
+      if epoch != fork_epoch() {
+        self.reseed()?;
+      }
+    }
+    if let Some(interval) = self.reseed_interval {
+      if self.since_reseed >= interval {
+        self.reseed()?;
+      }
+    }
+    let mut o = 0;
+    while o < out.len() {
+      if self.cur >= BUF_LEN {
+        self.refill();
+      }
+      let n = (out.len() - o).min(BUF_LEN - self.cur);
+      out[o .. o + n].copy_from_slice(&self.buf[self.cur .. self.cur + n]);
+      zero_volatile(&mut self.buf[self.cur .. self.cur + n]);
+      self.cur += n;
+      o += n;
+    }
+    self.since_reseed = self.since_reseed.saturating_add(out.len() as u64);
+    Ok(())
+  }
+}
+
+impl Read for ChaChaDrbg {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+    self.fill(buf)?;
+    Ok(buf.len())
+  }
+}
+
+impl Stream for ChaChaDrbg {
+  #[inline]
+  fn next_u8(&mut self) -> u8 {
+    let mut buf = [0; 1];
+    self.fill_bytes(&mut buf);
+    buf[0]
+  }
+
+  fn fill_bytes(&mut self, buf: &mut [u8]) {
+    self.fill(buf).unwrap();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+use super::{ChaChaDrbg, BUF_LEN, KEY_LEN};
+use crate::chacha20::{ChaCha20Generator, ChaCha20Stream};
+use crate::os::tests::{fork_and_read};
+
+use std::io::{Read};
+
+#[test]
+fn test_chacha_drbg_fast_key_erasure() {
+  let key = [3; 32];
+  let mut drbg = ChaChaDrbg::new(key);
+  let mut out = [0; 100];
+  drbg.read_exact(&mut out).unwrap();
+  let mut keystream = [0; BUF_LEN];
+  ChaCha20Stream::new(ChaCha20Generator::from_parts(b"expand 32-byte k", key, 0, 0)).read_exact(&mut keystream).unwrap();
+  assert_eq!(&drbg.key[..], &keystream[.. KEY_LEN]);
+  assert_eq!(&out[..], &keystream[KEY_LEN .. KEY_LEN + 100]);
+  assert!(drbg.buf[.. KEY_LEN + 100].iter().all(|&x| x == 0));
+  assert_eq!(&drbg.buf[KEY_LEN + 100 ..], &keystream[KEY_LEN + 100 ..]);
+  let mut rest = vec![0; BUF_LEN - KEY_LEN - 100 + 10];
+  drbg.read_exact(&mut rest).unwrap();
+  assert_eq!(&rest[.. BUF_LEN - KEY_LEN - 100], &keystream[KEY_LEN + 100 ..]);
+  assert_ne!(&drbg.key[..], &keystream[.. KEY_LEN]);
+}
+
+#[test]
+fn test_chacha_drbg_reseed() {
+  let mut drbg1 = ChaChaDrbg::new([3; 32]);
+  let mut drbg2 = ChaChaDrbg::new([3; 32]);
+  let mut out1 = [0; 64];
+  let mut out2 = [0; 64];
+  drbg1.read_exact(&mut out1).unwrap();
+  drbg2.read_exact(&mut out2).unwrap();
+  assert_eq!(out1, out2);
+  drbg2.set_reseed_interval(Some(64));
+  drbg1.read_exact(&mut out1).unwrap();
+  drbg2.read_exact(&mut out2).unwrap();
+  assert_ne!(out1, out2);
+  assert_eq!(drbg2.since_reseed, 64);
+}
+
+#[test]
+fn test_chacha_drbg_fork_diverges() {
+  let mut drbg = ChaChaDrbg::from_os().unwrap();
+  let mut out = [0; 32];
+  drbg.read_exact(&mut out).unwrap();
+  let (parent, child) = fork_and_read(&mut drbg);
+  assert_ne!(parent, child);
+}
+}
@@ -0,0 +1,229 @@
+use crate::{Stream};
+
+use std::fs::{File};
+use std::io::{Read, Write, Seek, SeekFrom, BufWriter, Error as IoError, ErrorKind as IoErrorKind};
+use std::path::{Path};
+
+/// Reads randomness from a file, e.g. one written by a `Recorder`.
+///
+/// Running past the end of the file is an `UnexpectedEof` error, unless
+/// wrap-around is enabled with `set_wrap`, in which case reading restarts at
+/// the beginning of the file. The `Stream` methods panic on either error.
+pub struct FileStream<F = File> {
+  file: F,
+  wrap: bool,
+}
+
+impl FileStream<File> {
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<FileStream<File>, IoError> {
+    Ok(FileStream::new(File::open(path)?))
+  }
+}
+
+impl<F: Read + Seek> FileStream<F> {
+  pub fn new(file: F) -> FileStream<F> {
+    FileStream{file, wrap: false}
+  }
+
+  pub fn set_wrap(&mut self, wrap: bool) {
+    self.wrap = wrap;
+  }
+
+  pub fn into_inner(self) -> F {
+    self.file
+  }
+}
+
+impl<F: Read + Seek> Read for FileStream<F> {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+    if buf.is_empty() {
+      return Ok(0);
+    }
+    let n = self.file.read(buf)?;
+    if n > 0 {
+      return Ok(n);
+    }
+    if !self.wrap {
+      return Err(IoError::new(IoErrorKind::UnexpectedEof, "FileStream: randomness file exhausted"));
+    }
+    self.file.seek(SeekFrom::Start(0))?;
+    let n = self.file.read(buf)?;
+    if n == 0 {
+      return Err(IoError::new(IoErrorKind::UnexpectedEof, "FileStream: randomness file is empty"));
+    }
+    Ok(n)
+  }
+}
+
+impl<F: Read + Seek> Stream for FileStream<F> {
+  #[inline]
+  fn next_u8(&mut self) -> u8 {
+    let mut buf = [0; 1];
+    self.fill_bytes(&mut buf);
+    buf[0]
+  }
+
+  fn fill_bytes(&mut self, buf: &mut [u8]) {
+    self.read_exact(buf).unwrap();
+  }
+}
+
+/// Tees every byte drawn from the wrapped stream into `sink`.
+///
+/// `Stream` draws are recorded as little endian bytes of the returned values,
+/// so replaying the recording through a `FileStream` with the same sequence
+/// of calls reproduces the run exactly, even for raw generators whose
+/// `next_u8` discards part of a word. A failed write panics on the `Stream`
+/// methods and is returned as an error by `Read`.
+pub struct Recorder<S, W = BufWriter<File>> {
+  inner: S,
+  sink:  W,
+}
+
+impl<S> Recorder<S, BufWriter<File>> {
+  pub fn create<P: AsRef<Path>>(inner: S, path: P) -> Result<Recorder<S, BufWriter<File>>, IoError> {
+    Ok(Recorder::new(inner, BufWriter::new(File::create(path)?)))
+  }
+}
+
+impl<S, W: Write> Recorder<S, W> {
+  pub fn new(inner: S, sink: W) -> Recorder<S, W> {
+    Recorder{inner, sink}
+  }
+
+  pub fn flush(&mut self) -> Result<(), IoError> {
+    self.sink.flush()
+  }
+
+  pub fn into_inner(mut self) -> Result<(S, W), IoError> {
+    self.sink.flush()?;
+    Ok((self.inner, self.sink))
+  }
+
+  #[inline]
+  fn record(&mut self, buf: &[u8]) {
+    self.sink.write_all(buf).unwrap();
+  }
+}
+
+impl<S: Read, W: Write> Read for Recorder<S, W> {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+    let n = self.inner.read(buf)?;
+    self.sink.write_all(&buf[ .. n])?;
+    Ok(n)
+  }
+}
+
+impl<S: Stream, W: Write> Stream for Recorder<S, W> {
+  #[inline]
+  fn next_u8(&mut self) -> u8 {
+    let x = self.inner.next_u8();
+    self.record(&[x]);
+    x
+  }
+
+  #[inline]
+  fn next_u16(&mut self) -> u16 {
+    let x = self.inner.next_u16();
+    self.record(&x.to_le_bytes());
+    x
+  }
+
+  #[inline]
+  fn next_u32(&mut self) -> u32 {
+    let x = self.inner.next_u32();
+    self.record(&x.to_le_bytes());
+    x
+  }
+
+  #[inline]
+  fn next_u64(&mut self) -> u64 {
+    let x = self.inner.next_u64();
+    self.record(&x.to_le_bytes());
+    x
+  }
+
+  fn fill_bytes(&mut self, buf: &mut [u8]) {
+    self.inner.fill_bytes(buf);
+    self.record(buf);
+  }
+
+  fn fill_u32(&mut self, buf: &mut [u32]) {
+    self.inner.fill_u32(buf);
+    for x in buf.iter() {
+      self.record(&x.to_le_bytes());
+    }
+  }
+
+  fn fill_u64(&mut self, buf: &mut [u64]) {
+    self.inner.fill_u64(buf);
+    for x in buf.iter() {
+      self.record(&x.to_le_bytes());
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+use super::{FileStream, Recorder};
+use crate::{Stream};
+use crate::chacha20::{ChaCha20Generator, ChaCha20Stream};
+use crate::dist::{Draw};
+use crate::xorshift::{Xoroshiro1024Generator};
+
+use std::env;
+use std::fs;
+use std::io::{Read, Cursor, ErrorKind as IoErrorKind};
+
+fn draws<S: Stream>(mut rng: S) -> Vec<u64> {
+  let mut xs = vec![
+      rng.next_u8() as u64,
+      rng.next_u16() as u64,
+      rng.next_u32() as u64,
+      rng.next_u64(),
+      (0 .. 1000_u32).draw(&mut rng) as u64,
+  ];
+  let mut buf = [0; 3];
+  rng.fill_u64(&mut buf);
+  xs.extend_from_slice(&buf);
+  xs
+}
+
+#[test]
+fn test_recorder_replay() {
+  let path = env::temp_dir().join(format!("rng-recorder-{}", std::process::id()));
+  let mut seed = ChaCha20Stream::new(ChaCha20Generator::from_parts(b"expand 32-byte k", [5; 32], 0, 0));
+  let gen = Xoroshiro1024Generator::from(&mut seed as &mut dyn Read);
+  let mut rec = Recorder::create(gen, &path).unwrap();
+  let expected = draws(&mut rec);
+  rec.flush().unwrap();
+  let replayed = draws(FileStream::open(&path).unwrap());
+  fs::remove_file(&path).unwrap();
+  assert_eq!(replayed, expected);
+}
+
+#[test]
+fn test_recorder_read() {
+  let mut rec = Recorder::new(ChaCha20Stream::new(ChaCha20Generator::from_parts(b"expand 32-byte k", [5; 32], 0, 0)), Vec::new());
+  let mut buf = [0; 100];
+  rec.read_exact(&mut buf).unwrap();
+  let (_, log) = rec.into_inner().unwrap();
+  assert_eq!(&log[..], &buf[..]);
+}
+
+#[test]
+fn test_file_stream_eof_and_wrap() {
+  let mut stream = FileStream::new(Cursor::new(vec![1, 2, 3]));
+  let mut buf = [0; 5];
+  let e = stream.read_exact(&mut buf).unwrap_err();
+  assert_eq!(e.kind(), IoErrorKind::UnexpectedEof);
+  let mut stream = FileStream::new(Cursor::new(vec![1, 2, 3]));
+  stream.set_wrap(true);
+  stream.read_exact(&mut buf).unwrap();
+  assert_eq!(buf, [1, 2, 3, 1, 2]);
+  assert_eq!(stream.next_u16(), 0x0103);
+  let mut stream = FileStream::new(Cursor::new(vec![]));
+  stream.set_wrap(true);
+  assert_eq!(stream.read(&mut buf).unwrap_err().kind(), IoErrorKind::UnexpectedEof);
+}
+}
@@ -0,0 +1,68 @@
+// Arithmetic on polynomials over GF(2) modulo a characteristic polynomial,
+// for jumping linear generators ahead by an arbitrary number of steps.
+//
+// A modulus of degree `64 * N` is stored as its `N` low words, with the
+// leading term implicit; residues are stored in `N` words as well, with the
+// coefficient of x^i in bit `i % 64` of word `i / 64`.
+
+/// Returns `a * b mod p`.
+pub(crate) fn mulmod<const N: usize>(a: &[u64; N], b: &[u64; N], p: &[u64; N]) -> [u64; N] {
+  let mut r = [0; N];
+  for i in (0 .. N).rev() {
+    for bit in (0 .. 64).rev() {
+      // r = r * x mod p
+      let carry = r[N - 1] >> 63;
+      for j in (1 .. N).rev() {
+        r[j] = (r[j] << 1) | (r[j - 1] >> 63);
+      }
+      r[0] <<= 1;
+      if carry != 0 {
+        for j in 0 .. N {
+          r[j] ^= p[j];
+        }
+      }
+      if (b[i] >> bit) & 1 != 0 {
+        for j in 0 .. N {
+          r[j] ^= a[j];
+        }
+      }
+    }
+  }
+  r
+}
+
+/// Returns `base^k mod p`.
+pub(crate) fn powmod<const N: usize>(base: &[u64; N], k: u128, p: &[u64; N]) -> [u64; N] {
+  let mut r = [0; N];
+  r[0] = 1;
+  for bit in (0 .. 128 - k.leading_zeros()).rev() {
+    r = mulmod(&r, &r, p);
+    if (k >> bit) & 1 != 0 {
+      r = mulmod(&r, base, p);
+    }
+  }
+  r
+}
+
+/// Returns x^k mod p, the jump polynomial for `k` steps.
+pub(crate) fn x_pow<const N: usize>(k: u128, p: &[u64; N]) -> [u64; N] {
+  let mut x = [0; N];
+  x[0] = 2;
+  powmod(&x, k, p)
+}
+
+/// Returns x^-k mod p, the jump polynomial for `k` steps backwards.
+///
+/// The characteristic polynomial of a full-period generator has constant
+/// term 1, so x * ((p + 1) / x) = 1 mod p, i.e. the inverse of x is `p`
+/// shifted right by one, with the implicit leading term moved into the top
+/// bit.
+pub(crate) fn x_pow_inv<const N: usize>(k: u128, p: &[u64; N]) -> [u64; N] {
+  debug_assert_eq!(p[0] & 1, 1);
+  let mut x_inv = [0; N];
+  for j in 0 .. N - 1 {
+    x_inv[j] = (p[j] >> 1) | (p[j + 1] << 63);
+  }
+  x_inv[N - 1] = (p[N - 1] >> 1) | (1 << 63);
+  powmod(&x_inv, k, p)
+}
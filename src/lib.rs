@@ -1,77 +1,235 @@
 extern crate byteorder;
 //extern crate getrandom;
 extern crate libc;
+#[cfg(feature = "serde")]
+extern crate serde;
 
-use std::io::{Read, Seek, SeekFrom, Error as IoError};
+use crate::snapshot::{invalid_snapshot};
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "serde")]
+use std::convert::{TryFrom};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::io::{Read, Seek, SeekFrom, Error as IoError, ErrorKind as IoErrorKind};
 use std::mem::{size_of};
+use std::ops::{Deref, DerefMut};
+use std::ptr::{write_volatile};
 use std::slice::{from_raw_parts};
+use std::sync::atomic::{compiler_fence, Ordering};
+
+macro_rules! impl_stream_gen32 {
+  ($gen:ty) => {
+    /// Raw generators have no buffer, so `next_u8` and `next_u16` discard the
+    /// upper bits of the generated word.
+    impl $crate::Stream for $gen {
+      #[inline]
+      fn next_u8(&mut self) -> u8 {
+        self.next_u32() as u8
+      }
+
+      #[inline]
+      fn next_u16(&mut self) -> u16 {
+        self.next_u32() as u16
+      }
+
+      #[inline]
+      fn next_u32(&mut self) -> u32 {
+        let mut out = [0];
+        self.next_gen(&mut out);
+        out[0]
+      }
+
+      #[inline]
+      fn next_u64(&mut self) -> u64 {
+        let lo = self.next_u32() as u64;
+        let hi = self.next_u32() as u64;
+        lo | (hi << 32)
+      }
+    }
+  };
+}
+
+macro_rules! impl_stream_gen64 {
+  ($gen:ty) => {
+    /// Raw generators have no buffer, so `next_u8`, `next_u16` and `next_u32`
+    /// discard the upper bits of the generated word.
+    impl $crate::Stream for $gen {
+      #[inline]
+      fn next_u8(&mut self) -> u8 {
+        self.next_u64() as u8
+      }
+
+      #[inline]
+      fn next_u16(&mut self) -> u16 {
+        self.next_u64() as u16
+      }
+
+      #[inline]
+      fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+      }
+
+      #[inline]
+      fn next_u64(&mut self) -> u64 {
+        let mut out = [0];
+        self.next_gen(&mut out);
+        out[0]
+      }
+    }
+  };
+}
 
 pub mod chacha20;
+pub mod chacha20poly1305;
+mod chacha20_simd;
 pub mod dist;
+pub mod drbg;
+pub mod file;
+mod gf2;
 pub mod os;
+pub mod pcg;
+pub mod poly1305;
+pub mod reseeding;
 pub mod romu;
+pub mod snapshot;
 pub mod splitmix;
-//pub mod urandom;
+pub mod thread;
 pub mod xorshift;
 
+pub use crate::thread::{ThreadRng, random, thread_rng};
+
 pub trait Generator<U> {
   fn next_gen(&mut self, out: &mut U);
+
+  /// Like `next_gen`, but reports an exhausted generator as an error
+  /// instead of panicking.
+  #[inline]
+  fn try_next_gen(&mut self, out: &mut U) -> Result<(), IoError> {
+    self.next_gen(out);
+    Ok(())
+  }
 }
 
-/*impl<G: Generator<[u32; 1]>> Iterator for G {
-  type Item = u32;
+/// Infallible draws of little endian integers. On the buffered streams these
+/// are consistent with the byte stream view, e.g. `next_u32` yields the same
+/// value as reading 4 bytes via `Read`.
+///
+/// The wider draws default to `fill_bytes`, so a byte source only needs to
+/// provide `next_u8` and `fill_bytes`.
+pub trait Stream {
+  fn next_u8(&mut self) -> u8;
 
   #[inline]
-  fn next(&mut self) -> Option<u32> {
-    let mut out = [0];
-    self.next_gen(&mut out);
-    Some(out[0])
+  fn next_u16(&mut self) -> u16 {
+    let mut buf = [0; 2];
+    self.fill_bytes(&mut buf);
+    u16::from_le_bytes(buf)
   }
-}
 
-impl<G: Generator<[u64; 1]>> Iterator for G {
-  type Item = u64;
+  #[inline]
+  fn next_u32(&mut self) -> u32 {
+    let mut buf = [0; 4];
+    self.fill_bytes(&mut buf);
+    u32::from_le_bytes(buf)
+  }
 
   #[inline]
-  fn next(&mut self) -> Option<u64> {
-    let mut out = [0];
-    self.next_gen(&mut out);
-    Some(out[0])
+  fn next_u64(&mut self) -> u64 {
+    let mut buf = [0; 8];
+    self.fill_bytes(&mut buf);
+    u64::from_le_bytes(buf)
   }
-}*/
 
-/*pub trait Stream {
-  fn next_u8(&mut self) -> u8;
+  #[inline]
+  fn fill_bytes(&mut self, buf: &mut [u8]) {
+    for x in buf.iter_mut() {
+      *x = self.next_u8();
+    }
+  }
+
+  #[inline]
+  fn fill_u32(&mut self, buf: &mut [u32]) {
+    for x in buf.iter_mut() {
+      *x = self.next_u32();
+    }
+  }
+
+  #[inline]
+  fn fill_u64(&mut self, buf: &mut [u64]) {
+    for x in buf.iter_mut() {
+      *x = self.next_u64();
+    }
+  }
+}
 
+impl<S: Stream + ?Sized> Stream for &mut S {
+  #[inline]
+  fn next_u8(&mut self) -> u8 {
+    (**self).next_u8()
+  }
+
+  #[inline]
   fn next_u16(&mut self) -> u16 {
-    u16::from_le_bytes([
-         self.next_u8()
-        ,self.next_u8()
-    ])
+    (**self).next_u16()
   }
 
+  #[inline]
   fn next_u32(&mut self) -> u32 {
-    u32::from_le_bytes([
-         self.next_u8()
-        ,self.next_u8()
-        ,self.next_u8()
-        ,self.next_u8()
-    ])
+    (**self).next_u32()
   }
 
+  #[inline]
   fn next_u64(&mut self) -> u64 {
-    u64::from_le_bytes([
-         self.next_u8()
-        ,self.next_u8()
-        ,self.next_u8()
-        ,self.next_u8()
-        ,self.next_u8()
-        ,self.next_u8()
-        ,self.next_u8()
-        ,self.next_u8()
-    ])
-  }
-}*/
+    (**self).next_u64()
+  }
+
+  #[inline]
+  fn fill_bytes(&mut self, buf: &mut [u8]) {
+    (**self).fill_bytes(buf)
+  }
+
+  #[inline]
+  fn fill_u32(&mut self, buf: &mut [u32]) {
+    (**self).fill_u32(buf)
+  }
+
+  #[inline]
+  fn fill_u64(&mut self, buf: &mut [u64]) {
+    (**self).fill_u64(buf)
+  }
+}
+
+/// Adapts an arbitrary reader, e.g. a randomness file, into a `Stream`.
+/// Panics if the underlying reader fails or runs out of bytes.
+pub struct ReadStream<R> {
+  inner: R,
+}
+
+impl<R: Read> ReadStream<R> {
+  pub fn new(inner: R) -> ReadStream<R> {
+    ReadStream{inner}
+  }
+
+  pub fn into_inner(self) -> R {
+    self.inner
+  }
+}
+
+impl<R: Read> Stream for ReadStream<R> {
+  #[inline]
+  fn next_u8(&mut self) -> u8 {
+    let mut buf = [0; 1];
+    self.fill_bytes(&mut buf);
+    buf[0]
+  }
+
+  #[inline]
+  fn fill_bytes(&mut self, buf: &mut [u8]) {
+    self.inner.read_exact(buf).unwrap();
+  }
+}
 
 #[inline]
 pub fn u32_slice_bytes_len(ubuf: &[u32]) -> usize {
@@ -93,12 +251,181 @@ pub fn u64_slice_as_bytes(ubuf: &[u64]) -> &[u8] {
   unsafe { from_raw_parts(ubuf.as_ptr() as *const _, ubuf.len() * size_of::<u64>()) }
 }
 
+/// Overwrites `buf` with zeros using volatile writes, so that the compiler
+/// cannot elide the wipe of a buffer that is about to be dropped.
+pub fn zero_volatile<T: Copy + Default>(buf: &mut [T]) {
+  for x in buf.iter_mut() {
+    unsafe { write_volatile(x, T::default()) };
+  }
+  compiler_fence(Ordering::SeqCst);
+}
+
+/// Wipes secret state in place with volatile writes. The value must not be
+/// used for generation afterwards.
+pub trait Zeroize {
+  fn zeroize(&mut self);
+}
+
+impl<T: Copy + Default, const N: usize> Zeroize for [T; N] {
+  fn zeroize(&mut self) {
+    zero_volatile(self);
+  }
+}
+
+/// Generators whose state can be refreshed with external entropy.
+pub trait Reseed {
+  /// Mixes key material read from `seed` into the state. Previously
+  /// buffered output is discarded.
+  fn reseed<R: Read>(&mut self, seed: R) -> Result<(), IoError>;
+}
+
+/// Opt-in wrapper that zeroizes the wrapped generator or stream on drop.
+pub struct Zeroizing<T: Zeroize>(T);
+
+impl<T: Zeroize> Zeroizing<T> {
+  pub fn new(inner: T) -> Zeroizing<T> {
+    Zeroizing(inner)
+  }
+}
+
+impl<T: Zeroize> Drop for Zeroizing<T> {
+  fn drop(&mut self) {
+    self.0.zeroize();
+  }
+}
+
+impl<T: Zeroize> Deref for Zeroizing<T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    &self.0
+  }
+}
+
+impl<T: Zeroize> DerefMut for Zeroizing<T> {
+  fn deref_mut(&mut self) -> &mut T {
+    &mut self.0
+  }
+}
+
+impl<T: Zeroize + Debug> Debug for Zeroizing<T> {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    self.0.fmt(f)
+  }
+}
+
+fn invalid_seek() -> IoError {
+  IoError::new(IoErrorKind::InvalidInput, "invalid seek to a negative or overflowing position")
+}
+
+pub(crate) fn seek_offset(base: u64, off: i64) -> Result<u64, IoError> {
+  let p = if off >= 0 {
+    base.checked_add(off as u64)
+  } else {
+    base.checked_sub(off.unsigned_abs())
+  };
+  p.ok_or_else(invalid_seek)
+}
+
+/// Resolves `SeekFrom::End(off)` against an end position of 2^64 bytes,
+/// i.e. one past the last position addressable by a `u64`.
+pub(crate) fn seek_offset_from_u64_end(off: i64) -> Result<u64, IoError> {
+  if off >= 0 {
+    return Err(invalid_seek());
+  }
+  Ok(u64::MAX - (off.unsigned_abs() - 1))
+}
+
+/// Resolves a seek on a generator that counts the words produced since
+/// seeding modulo 2^64, and can move to any word, to the target word.
+/// Positions are in bytes and must be aligned to a word. There is no end to
+/// seek from, since the period does not fit in a 64-bit byte position.
+pub(crate) fn seek_word(pos: u64, word_bytes: u64, to: SeekFrom) -> Result<u64, IoError> {
+  let p = match to {
+    SeekFrom::Start(p) => p,
+    SeekFrom::Current(off) => seek_offset(word_stream_position(pos, word_bytes)?, off)?,
+    SeekFrom::End(_) => {
+      return Err(IoError::new(IoErrorKind::InvalidInput, "generator has no end to seek from"));
+    }
+  };
+  if p % word_bytes != 0 {
+    return Err(IoError::new(IoErrorKind::InvalidInput, "seek position is not aligned to an output word"));
+  }
+  Ok(p / word_bytes)
+}
+
+/// The byte position of a generator that seeks with `seek_word`.
+pub(crate) fn word_stream_position(pos: u64, word_bytes: u64) -> Result<u64, IoError> {
+  pos.checked_mul(word_bytes).ok_or_else(|| IoError::other("stream position does not fit in 64 bits"))
+}
+
+// Buffers can hold keystream, and comparing them is not constant-time, so
+// equality is for tests only, as for the ChaCha generators.
+#[cfg_attr(test, derive(PartialEq, Eq))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(
+    try_from = "Buffer32State<R, U>",
+    bound(deserialize = "R: Deserialize<'de>, U: Deserialize<'de> + AsRef<[u32]>")))]
 pub struct Buffer32<R, U> {
   gen:  R,
   ubuf: U,
   cur:  usize,
 }
 
+/// Unchecked form of `Buffer32`, for deserialization.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "Buffer32")]
+struct Buffer32State<R, U> {
+  gen:  R,
+  ubuf: U,
+  cur:  usize,
+}
+
+#[cfg(feature = "serde")]
+impl<R, U: AsRef<[u32]>> TryFrom<Buffer32State<R, U>> for Buffer32<R, U> {
+  type Error = IoError;
+
+  fn try_from(raw: Buffer32State<R, U>) -> Result<Buffer32<R, U>, IoError> {
+    Buffer32::try_from_parts(raw.gen, raw.ubuf, raw.cur)
+  }
+}
+
+impl<R, U: AsRef<[u32]>> Buffer32<R, U> {
+  /// Rejects a cursor past the end of the buffer.
+  fn try_from_parts(gen: R, ubuf: U, cur: usize) -> Result<Buffer32<R, U>, IoError> {
+    if cur > u32_slice_bytes_len(ubuf.as_ref()) {
+      return Err(invalid_snapshot(format!("Buffer32 cursor {} is out of range", cur)));
+    }
+    Ok(Buffer32{gen, ubuf, cur})
+  }
+}
+
+impl<R: Zeroize, U: Zeroize> Zeroize for Buffer32<R, U> {
+  fn zeroize(&mut self) {
+    self.gen.zeroize();
+    self.ubuf.zeroize();
+    unsafe { write_volatile(&mut self.cur, 0) };
+    compiler_fence(Ordering::SeqCst);
+  }
+}
+
+impl<R: Reseed, U: AsRef<[u32]> + Zeroize> Reseed for Buffer32<R, U> {
+  fn reseed<S: Read>(&mut self, seed: S) -> Result<(), IoError> {
+    self.gen.reseed(seed)?;
+    self.ubuf.zeroize();
+    self.cur = u32_slice_bytes_len(self.ubuf.as_ref());
+    Ok(())
+  }
+}
+
+/// Only the generator and the cursor are shown, never the buffered output.
+impl<R: Debug, U> Debug for Buffer32<R, U> {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    f.debug_struct("Buffer32").field("gen", &self.gen).field("cur", &self.cur).finish()
+  }
+}
+
 impl<R, U: AsRef<[u32]> + Default> Buffer32<R, U> {
   pub fn new(gen: R) -> Buffer32<R, U> {
     let ubuf = U::default();
@@ -123,7 +450,9 @@ impl<R: Generator<U>, U: AsRef<[u32]>> Iterator for Buffer32<R, U> {
   fn next(&mut self) -> Option<u8> {
     let len = u32_slice_bytes_len(self.ubuf.as_ref());
     if self.cur >= len {
-      self.gen.next_gen(&mut self.ubuf);
+      if self.gen.try_next_gen(&mut self.ubuf).is_err() {
+        return None;
+      }
       self.cur = 1;
       return Some(self.ubuf.as_ref()[0] as u8);
     }
@@ -133,33 +462,121 @@ impl<R: Generator<U>, U: AsRef<[u32]>> Iterator for Buffer32<R, U> {
   }
 }
 
-impl<R: Generator<U>, U: AsRef<[u32]>> Read for Buffer32<R, U> {
+impl<R: Generator<U>, U: AsRef<[u32]>> Buffer32<R, U> {
   #[inline]
+  fn drain_buf(&mut self, buf: &mut [u8]) -> usize {
+    let ubuf = self.ubuf.as_ref();
+    let len = u32_slice_bytes_len(ubuf);
+    let mut o = 0;
+    while o < buf.len() && self.cur < len {
+      buf[o] = (ubuf[self.cur / 4] >> ((self.cur % 4) * 8)) as u8;
+      self.cur += 1;
+      o += 1;
+    }
+    o
+  }
+}
+
+impl<R: Generator<U>, U: AsRef<[u32]>> Buffer32<R, U> {
+  /// XORs the stream, starting at the current position, into `buf`; i.e.
+  /// encrypts or decrypts `buf` in place when the stream is a keystream.
+  pub fn apply_keystream(&mut self, buf: &mut [u8]) -> Result<(), IoError> {
+    let mut ks = [0; 256];
+    for chunk in buf.chunks_mut(ks.len()) {
+      let ks = &mut ks[ .. chunk.len()];
+      self.read_exact(ks)?;
+      for (x, &k) in chunk.iter_mut().zip(ks.iter()) {
+        *x ^= k;
+      }
+    }
+    Ok(())
+  }
+}
+
+impl<R: Generator<U>, U: AsRef<[u32]>> Read for Buffer32<R, U> {
   fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
-    for x in buf.iter_mut() {
-      *x = self.next().unwrap();
+    let len = u32_slice_bytes_len(self.ubuf.as_ref());
+    let mut o = self.drain_buf(buf);
+    while buf.len() - o >= len {
+      if let Err(e) = self.gen.try_next_gen(&mut self.ubuf) {
+        return if o > 0 { Ok(o) } else { Err(e) };
+      }
+      for (dst, &x) in buf[o .. o + len].chunks_exact_mut(4).zip(self.ubuf.as_ref().iter()) {
+        dst.copy_from_slice(&x.to_le_bytes());
+      }
+      o += len;
+    }
+    if o < buf.len() {
+      if let Err(e) = self.gen.try_next_gen(&mut self.ubuf) {
+        return if o > 0 { Ok(o) } else { Err(e) };
+      }
+      self.cur = 0;
+      o += self.drain_buf(&mut buf[o .. ]);
     }
+    debug_assert_eq!(o, buf.len());
     Ok(buf.len())
   }
 }
 
+impl<R: Generator<U>, U: AsRef<[u32]>> Stream for Buffer32<R, U> {
+  #[inline]
+  fn next_u8(&mut self) -> u8 {
+    self.next().unwrap()
+  }
+
+  #[inline]
+  fn next_u32(&mut self) -> u32 {
+    let len = u32_slice_bytes_len(self.ubuf.as_ref());
+    if self.cur >= len {
+      self.gen.next_gen(&mut self.ubuf);
+      self.cur = 0;
+    }
+    if self.cur.is_multiple_of(4) && self.cur + 4 <= len {
+      let x = self.ubuf.as_ref()[self.cur / 4];
+      self.cur += 4;
+      return x;
+    }
+    let mut buf = [0; 4];
+    self.fill_bytes(&mut buf);
+    u32::from_le_bytes(buf)
+  }
+
+  #[inline]
+  fn fill_bytes(&mut self, buf: &mut [u8]) {
+    self.read_exact(buf).unwrap();
+  }
+}
+
 impl<R: Generator<U> + Seek, U: AsRef<[u32]>> Seek for Buffer32<R, U> {
+  /// `SeekFrom::End` assumes that the end of the underlying generator is
+  /// aligned to the buffer length.
   fn seek(&mut self, pos: SeekFrom) -> Result<u64, IoError> {
     let ubuf_len = u32_slice_bytes_len(self.ubuf.as_ref());
     match pos {
       SeekFrom::Start(p) => {
         let p_rem = p % (ubuf_len as u64);
         self.gen.seek(SeekFrom::Start(p - p_rem))?;
-        self.cur = match p_rem {
-          0 => ubuf_len,
-          _ => {
-            self.gen.next_gen(&mut self.ubuf);
-            p_rem as usize
-          }
-        };
+        self.cur = ubuf_len;
+        if p_rem != 0 {
+          self.gen.try_next_gen(&mut self.ubuf)?;
+          self.cur = p_rem as usize;
+        }
         Ok(p)
       }
-      _ => unimplemented!()
+      SeekFrom::Current(off) => {
+        let p = seek_offset(self.stream_position()?, off)?;
+        self.seek(SeekFrom::Start(p))
+      }
+      SeekFrom::End(off) => {
+        let off_rem = off.rem_euclid(ubuf_len as i64);
+        let pbase = self.gen.seek(SeekFrom::End(off - off_rem))?;
+        self.cur = ubuf_len;
+        if off_rem != 0 {
+          self.gen.try_next_gen(&mut self.ubuf)?;
+          self.cur = off_rem as usize;
+        }
+        Ok(pbase + off_rem as u64)
+      }
     }
   }
 
@@ -170,12 +587,72 @@ impl<R: Generator<U> + Seek, U: AsRef<[u32]>> Seek for Buffer32<R, U> {
   }
 }
 
+// Equality is for tests only, as for `Buffer32`.
+#[cfg_attr(test, derive(PartialEq, Eq))]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(
+    try_from = "Buffer64State<R, U>",
+    bound(deserialize = "R: Deserialize<'de>, U: Deserialize<'de> + AsRef<[u64]>")))]
 pub struct Buffer64<R, U> {
   gen:  R,
   ubuf: U,
   cur:  usize,
 }
 
+/// Unchecked form of `Buffer64`, for deserialization.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "Buffer64")]
+struct Buffer64State<R, U> {
+  gen:  R,
+  ubuf: U,
+  cur:  usize,
+}
+
+#[cfg(feature = "serde")]
+impl<R, U: AsRef<[u64]>> TryFrom<Buffer64State<R, U>> for Buffer64<R, U> {
+  type Error = IoError;
+
+  fn try_from(raw: Buffer64State<R, U>) -> Result<Buffer64<R, U>, IoError> {
+    Buffer64::try_from_parts(raw.gen, raw.ubuf, raw.cur)
+  }
+}
+
+impl<R, U: AsRef<[u64]>> Buffer64<R, U> {
+  /// Rejects a cursor past the end of the buffer.
+  fn try_from_parts(gen: R, ubuf: U, cur: usize) -> Result<Buffer64<R, U>, IoError> {
+    if cur > u64_slice_bytes_len(ubuf.as_ref()) {
+      return Err(invalid_snapshot(format!("Buffer64 cursor {} is out of range", cur)));
+    }
+    Ok(Buffer64{gen, ubuf, cur})
+  }
+}
+
+impl<R: Zeroize, U: Zeroize> Zeroize for Buffer64<R, U> {
+  fn zeroize(&mut self) {
+    self.gen.zeroize();
+    self.ubuf.zeroize();
+    unsafe { write_volatile(&mut self.cur, 0) };
+    compiler_fence(Ordering::SeqCst);
+  }
+}
+
+impl<R: Reseed, U: AsRef<[u64]> + Zeroize> Reseed for Buffer64<R, U> {
+  fn reseed<S: Read>(&mut self, seed: S) -> Result<(), IoError> {
+    self.gen.reseed(seed)?;
+    self.ubuf.zeroize();
+    self.cur = u64_slice_bytes_len(self.ubuf.as_ref());
+    Ok(())
+  }
+}
+
+/// Only the generator and the cursor are shown, never the buffered output.
+impl<R: Debug, U> Debug for Buffer64<R, U> {
+  fn fmt(&self, f: &mut Formatter) -> FmtResult {
+    f.debug_struct("Buffer64").field("gen", &self.gen).field("cur", &self.cur).finish()
+  }
+}
+
 impl<R, U: AsRef<[u64]> + Default> Buffer64<R, U> {
   pub fn new(gen: R) -> Buffer64<R, U> {
     let ubuf = U::default();
@@ -200,7 +677,9 @@ impl<R: Generator<U>, U: AsRef<[u64]>> Iterator for Buffer64<R, U> {
   fn next(&mut self) -> Option<u8> {
     let len = u64_slice_bytes_len(self.ubuf.as_ref());
     if self.cur >= len {
-      self.gen.next_gen(&mut self.ubuf);
+      if self.gen.try_next_gen(&mut self.ubuf).is_err() {
+        return None;
+      }
       self.cur = 1;
       return Some(self.ubuf.as_ref()[0] as u8);
     }
@@ -210,12 +689,338 @@ impl<R: Generator<U>, U: AsRef<[u64]>> Iterator for Buffer64<R, U> {
   }
 }
 
-impl<R: Generator<U>, U: AsRef<[u64]>> Read for Buffer64<R, U> {
+impl<R: Generator<U>, U: AsRef<[u64]>> Buffer64<R, U> {
   #[inline]
+  fn drain_buf(&mut self, buf: &mut [u8]) -> usize {
+    let ubuf = self.ubuf.as_ref();
+    let len = u64_slice_bytes_len(ubuf);
+    let mut o = 0;
+    while o < buf.len() && self.cur < len {
+      buf[o] = (ubuf[self.cur / 8] >> ((self.cur % 8) * 8)) as u8;
+      self.cur += 1;
+      o += 1;
+    }
+    o
+  }
+}
+
+impl<R: Generator<U>, U: AsRef<[u64]>> Read for Buffer64<R, U> {
   fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
-    for x in buf.iter_mut() {
-      *x = self.next().unwrap();
+    let len = u64_slice_bytes_len(self.ubuf.as_ref());
+    let mut o = self.drain_buf(buf);
+    while buf.len() - o >= len {
+      if let Err(e) = self.gen.try_next_gen(&mut self.ubuf) {
+        return if o > 0 { Ok(o) } else { Err(e) };
+      }
+      for (dst, &x) in buf[o .. o + len].chunks_exact_mut(8).zip(self.ubuf.as_ref().iter()) {
+        dst.copy_from_slice(&x.to_le_bytes());
+      }
+      o += len;
+    }
+    if o < buf.len() {
+      if let Err(e) = self.gen.try_next_gen(&mut self.ubuf) {
+        return if o > 0 { Ok(o) } else { Err(e) };
+      }
+      self.cur = 0;
+      o += self.drain_buf(&mut buf[o .. ]);
     }
+    debug_assert_eq!(o, buf.len());
     Ok(buf.len())
   }
 }
+
+impl<R: Generator<U>, U: AsRef<[u64]>> Stream for Buffer64<R, U> {
+  #[inline]
+  fn next_u8(&mut self) -> u8 {
+    self.next().unwrap()
+  }
+
+  #[inline]
+  fn next_u32(&mut self) -> u32 {
+    let len = u64_slice_bytes_len(self.ubuf.as_ref());
+    if self.cur >= len {
+      self.gen.next_gen(&mut self.ubuf);
+      self.cur = 0;
+    }
+    if self.cur.is_multiple_of(4) && self.cur + 4 <= len {
+      let x = (self.ubuf.as_ref()[self.cur / 8] >> ((self.cur % 8) * 8)) as u32;
+      self.cur += 4;
+      return x;
+    }
+    let mut buf = [0; 4];
+    self.fill_bytes(&mut buf);
+    u32::from_le_bytes(buf)
+  }
+
+  #[inline]
+  fn next_u64(&mut self) -> u64 {
+    let len = u64_slice_bytes_len(self.ubuf.as_ref());
+    if self.cur >= len {
+      self.gen.next_gen(&mut self.ubuf);
+      self.cur = 0;
+    }
+    if self.cur.is_multiple_of(8) && self.cur + 8 <= len {
+      let x = self.ubuf.as_ref()[self.cur / 8];
+      self.cur += 8;
+      return x;
+    }
+    let mut buf = [0; 8];
+    self.fill_bytes(&mut buf);
+    u64::from_le_bytes(buf)
+  }
+
+  #[inline]
+  fn fill_bytes(&mut self, buf: &mut [u8]) {
+    self.read_exact(buf).unwrap();
+  }
+}
+
+impl<R: Generator<U> + Seek, U: AsRef<[u64]>> Seek for Buffer64<R, U> {
+  /// `SeekFrom::End` assumes that the end of the underlying generator is
+  /// aligned to the buffer length.
+  fn seek(&mut self, pos: SeekFrom) -> Result<u64, IoError> {
+    let ubuf_len = u64_slice_bytes_len(self.ubuf.as_ref());
+    match pos {
+      SeekFrom::Start(p) => {
+        let p_rem = p % (ubuf_len as u64);
+        self.gen.seek(SeekFrom::Start(p - p_rem))?;
+        self.cur = ubuf_len;
+        if p_rem != 0 {
+          self.gen.try_next_gen(&mut self.ubuf)?;
+          self.cur = p_rem as usize;
+        }
+        Ok(p)
+      }
+      SeekFrom::Current(off) => {
+        let p = seek_offset(self.stream_position()?, off)?;
+        self.seek(SeekFrom::Start(p))
+      }
+      SeekFrom::End(off) => {
+        let off_rem = off.rem_euclid(ubuf_len as i64);
+        let pbase = self.gen.seek(SeekFrom::End(off - off_rem))?;
+        self.cur = ubuf_len;
+        if off_rem != 0 {
+          self.gen.try_next_gen(&mut self.ubuf)?;
+          self.cur = off_rem as usize;
+        }
+        Ok(pbase + off_rem as u64)
+      }
+    }
+  }
+
+  fn stream_position(&mut self) -> Result<u64, IoError> {
+    let ubuf_len = u64_slice_bytes_len(self.ubuf.as_ref());
+    let pbase = self.gen.stream_position()?;
+    Ok(pbase - (ubuf_len - self.cur) as u64)
+  }
+}
+
+/// Decodes a hex string, for test vectors.
+#[cfg(test)]
+pub(crate) fn hex_bytes(s: &str) -> Vec<u8> {
+  (0 .. s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i .. i + 2], 16).unwrap()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+use crate::chacha20::{ChaCha20Generator, ChaCha20Stream};
+use crate::splitmix::{Splitmix64Stream};
+use crate::xorshift::{Xoroshiro1024Generator, Xoroshiro1024Stream};
+use crate::{Stream};
+
+use std::io::{Read};
+
+fn check_read_matches_bytes<S: Read + Iterator<Item=u8>>(mut bulk: S, bytewise: S) {
+  let mut expected = Vec::new();
+  expected.extend(Iterator::take(bytewise, 4000));
+  let mut actual = vec![0; 4000];
+  let mut o = 0;
+  for &n in [0, 1, 3, 64, 7, 200, 129, 1000, 8, 9].iter().cycle() {
+    let n = n.min(actual.len() - o);
+    bulk.read_exact(&mut actual[o .. o + n]).unwrap();
+    o += n;
+    if o == actual.len() {
+      break;
+    }
+  }
+  assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_buffer32_read_matches_bytes() {
+  let key: [u8; 32] = [7; 32];
+  check_read_matches_bytes(
+      ChaCha20Stream::new(ChaCha20Generator::new_default(&key[..], 1, 0)),
+      ChaCha20Stream::new(ChaCha20Generator::new_default(&key[..], 1, 0)),
+  );
+}
+
+#[test]
+fn test_buffer64_read_matches_bytes() {
+  check_read_matches_bytes(Splitmix64Stream::from(0x1234_u64), Splitmix64Stream::from(0x1234_u64));
+  let mut state = [0; 16];
+  for (k, x) in state.iter_mut().enumerate() {
+    *x = (k as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15);
+  }
+  check_read_matches_bytes(
+      Xoroshiro1024Stream::new(Xoroshiro1024Generator::from(state)),
+      Xoroshiro1024Stream::new(Xoroshiro1024Generator::from(state)),
+  );
+}
+
+#[test]
+fn test_stream_matches_bytes() {
+  let key: [u8; 32] = [7; 32];
+  let mut words = ChaCha20Stream::new(ChaCha20Generator::new_default(&key[..], 1, 0));
+  let mut bytes = ChaCha20Stream::new(ChaCha20Generator::new_default(&key[..], 1, 0));
+  let mut buf = [0; 8];
+  for _ in 0 .. 40 {
+    bytes.read_exact(&mut buf[.. 4]).unwrap();
+    assert_eq!(words.next_u32(), u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]));
+    bytes.read_exact(&mut buf).unwrap();
+    assert_eq!(words.next_u64(), u64::from_le_bytes(buf));
+    bytes.read_exact(&mut buf[.. 1]).unwrap();
+    words.read_exact(&mut buf[1 .. 2]).unwrap();
+    assert_eq!(buf[0], buf[1]);
+  }
+  let mut words = Splitmix64Stream::from(0x1234_u64);
+  let mut bytes = Splitmix64Stream::from(0x1234_u64);
+  for _ in 0 .. 40 {
+    bytes.read_exact(&mut buf[.. 4]).unwrap();
+    assert_eq!(words.next_u32(), u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]));
+    bytes.read_exact(&mut buf).unwrap();
+    assert_eq!(words.next_u64(), u64::from_le_bytes(buf));
+    bytes.read_exact(&mut buf[.. 3]).unwrap();
+    words.read_exact(&mut buf[3 .. 6]).unwrap();
+    assert_eq!(&buf[.. 3], &buf[3 .. 6]);
+  }
+  let mut wbuf = [0; 5];
+  words.fill_u64(&mut wbuf);
+  for &w in wbuf.iter() {
+    bytes.read_exact(&mut buf).unwrap();
+    assert_eq!(w, u64::from_le_bytes(buf));
+  }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_generator_serde_tokens() {
+  use crate::chacha20::{ChaCha20WideStream};
+  use crate::romu::{Romu32x4Generator};
+  use crate::splitmix::{Splitmix64Generator};
+  use crate::xorshift::{Xorshiftplus128v1Generator};
+  use serde_test::{Token, assert_tokens};
+
+  let mut state = [0; 16];
+  for (k, x) in state.iter_mut().enumerate() {
+    *x = (k as u32) * 0x01010101;
+  }
+  let mut chacha = vec![
+      Token::Struct{name: "ChaCha20Generator", len: 2},
+      Token::Str("state"),
+      Token::Tuple{len: 16},
+  ];
+  chacha.extend(state.iter().map(|&x| Token::U32(x)));
+  chacha.extend(vec![Token::TupleEnd, Token::Str("end"), Token::Bool(false), Token::StructEnd]);
+  assert_tokens(&ChaCha20Generator::from(state), &chacha);
+  let mut wide = vec![
+      Token::Struct{name: "Buffer32", len: 3},
+      Token::Str("gen"),
+  ];
+  wide.extend(chacha.iter().cloned());
+  wide.extend(vec![Token::Str("ubuf"), Token::Seq{len: Some(128)}]);
+  wide.extend((0 .. 128).map(|_| Token::U32(0)));
+  wide.extend(vec![Token::SeqEnd, Token::Str("cur"), Token::U64(512), Token::StructEnd]);
+  assert_tokens(&ChaCha20WideStream::new(ChaCha20Generator::from(state)), &wide);
+  let mut xoroshiro = vec![
+      Token::Struct{name: "Xoroshiro1024Generator", len: 3},
+      Token::Str("state"),
+      Token::Tuple{len: 16},
+  ];
+  xoroshiro.extend((1 .. 17).map(Token::U64));
+  xoroshiro.extend(vec![Token::TupleEnd, Token::Str("cursor"), Token::U8(0), Token::Str("pos"), Token::U64(0), Token::StructEnd]);
+  let mut xstate = [0; 16];
+  for (k, x) in xstate.iter_mut().enumerate() {
+    *x = k as u64 + 1;
+  }
+  assert_tokens(&Xoroshiro1024Generator::from(xstate), &xoroshiro);
+  assert_tokens(&Xorshiftplus128v1Generator::from([1, 2]), &[
+      Token::Struct{name: "Xorshiftplus128v1Generator", len: 1},
+      Token::Str("state"),
+      Token::Tuple{len: 2}, Token::U64(1), Token::U64(2), Token::TupleEnd,
+      Token::StructEnd,
+  ]);
+  assert_tokens(&Romu32x4Generator::from([1, 2, 3, 4]), &[
+      Token::Struct{name: "Romu32x4Generator", len: 1},
+      Token::Str("state"),
+      Token::Tuple{len: 4}, Token::U32(1), Token::U32(2), Token::U32(3), Token::U32(4), Token::TupleEnd,
+      Token::StructEnd,
+  ]);
+  let mut stream = Splitmix64Stream::new(Splitmix64Generator::from(5));
+  stream.next_u16();
+  assert_tokens(&stream, &[
+      Token::Struct{name: "Buffer64", len: 3},
+      Token::Str("gen"),
+      Token::Struct{name: "Splitmix64Generator", len: 1},
+      Token::Str("state"), Token::U64(0x9e3779b97f4a7c1a),
+      Token::StructEnd,
+      Token::Str("ubuf"),
+      Token::Tuple{len: 1}, Token::U64(0x63033b0ca389c35a), Token::TupleEnd,
+      Token::Str("cur"), Token::U64(2),
+      Token::StructEnd,
+  ]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_generator_serde_rejects_invalid_states() {
+  use crate::pcg::{Pcg32Generator};
+  use crate::romu::{Romu32x4Generator};
+  use crate::splitmix::{Splitmix64Stream};
+  use crate::xorshift::{Xorshiftplus128v1Generator};
+  use serde_test::{Token, assert_de_tokens_error};
+
+  let xoroshiro = |state: u64, cursor: u8| {
+    let mut tokens = vec![
+        Token::Struct{name: "Xoroshiro1024Generator", len: 3},
+        Token::Str("state"),
+        Token::Tuple{len: 16},
+    ];
+    tokens.extend((0 .. 16).map(|_| Token::U64(state)));
+    tokens.extend(vec![Token::TupleEnd, Token::Str("cursor"), Token::U8(cursor), Token::Str("pos"), Token::U64(0), Token::StructEnd]);
+    tokens
+  };
+  assert_de_tokens_error::<Xoroshiro1024Generator>(&xoroshiro(1, 16), "Xoroshiro1024Generator cursor 16 is out of range");
+  assert_de_tokens_error::<Xoroshiro1024Generator>(&xoroshiro(0, 0), "Xoroshiro1024Generator has an all-zero state");
+  assert_de_tokens_error::<Xorshiftplus128v1Generator>(&[
+      Token::Struct{name: "Xorshiftplus128v1Generator", len: 1},
+      Token::Str("state"),
+      Token::Tuple{len: 2}, Token::U64(0), Token::U64(0), Token::TupleEnd,
+      Token::StructEnd,
+  ], "Xorshiftplus128v1Generator has an all-zero state");
+  assert_de_tokens_error::<Romu32x4Generator>(&[
+      Token::Struct{name: "Romu32x4Generator", len: 1},
+      Token::Str("state"),
+      Token::Tuple{len: 4}, Token::U32(0), Token::U32(0), Token::U32(0), Token::U32(0), Token::TupleEnd,
+      Token::StructEnd,
+  ], "Romu32x4Generator has an all-zero state");
+  assert_de_tokens_error::<Pcg32Generator>(&[
+      Token::Struct{name: "Pcg32Generator", len: 3},
+      Token::Str("state"), Token::U64(1),
+      Token::Str("inc"), Token::U64(2),
+      Token::Str("pos"), Token::U64(0),
+      Token::StructEnd,
+  ], "Pcg32Generator has an even increment");
+  assert_de_tokens_error::<Splitmix64Stream>(&[
+      Token::Struct{name: "Buffer64", len: 3},
+      Token::Str("gen"),
+      Token::Struct{name: "Splitmix64Generator", len: 1},
+      Token::Str("state"), Token::U64(1),
+      Token::StructEnd,
+      Token::Str("ubuf"),
+      Token::Tuple{len: 1}, Token::U64(0), Token::TupleEnd,
+      Token::Str("cur"), Token::U64(9),
+      Token::StructEnd,
+  ], "Buffer64 cursor 9 is out of range");
+}
+}
@@ -1,40 +1,170 @@
+use crate::{Stream};
+
 use std::cmp::{min};
-use std::io::{Read, Error as IoError};
+#[cfg(target_os = "linux")]
+use std::fs::{File};
+use std::io::{Read, Error as IoError, ErrorKind as IoErrorKind};
+#[cfg(target_os = "linux")]
+use std::os::unix::fs::{FileTypeExt};
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::sync::{Once};
+#[cfg(target_os = "linux")]
+use std::sync::atomic::{AtomicBool};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static FORK_EPOCH: AtomicUsize = AtomicUsize::new(0);
+#[cfg(target_os = "linux")]
+static GETRANDOM_UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+#[cfg(unix)]
+static FORK_HANDLER: Once = Once::new();
+
+#[cfg(unix)]
+extern "C" fn bump_fork_epoch() {
+  FORK_EPOCH.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Returns a counter that is incremented in the child process on every
+/// `fork`. Generators seeded from the OS remember the epoch at seeding time
+/// and reseed once it changes, so that parent and child never share output.
+///
+/// The first call registers a `pthread_atfork` handler; forks that happen
+/// before then are not counted.
+pub fn fork_epoch() -> usize {
+  #[cfg(unix)]
+  FORK_HANDLER.call_once(|| {
+    let ret = unsafe { libc::pthread_atfork(None, None, Some(bump_fork_epoch)) };
+    assert_eq!(ret, 0);
+  });
+  FORK_EPOCH.load(Ordering::SeqCst)
+}
 
 #[inline]
 pub fn getrandom(buf: &mut [u8]) -> Result<(), IoError> {
-  RandomStream{}.read(buf).map(|_| ())
+  RandomStream::default().read_exact(buf)
 }
 
 pub type GetrandomStream = RandomStream;
 
-#[derive(Default)]
+/// Reads from the OS entropy source, i.e. `getrandom(2)` on Linux and
+/// `getentropy(2)` on macOS.
+///
+/// By default reads block until the entropy pool is initialized. With
+/// `set_nonblock`, a read before then fails with `ErrorKind::WouldBlock`
+/// instead; with `set_random`, the bytes are drawn from the `/dev/random`
+/// pool (`GRND_RANDOM`). Both flags are Linux-only and ignored elsewhere.
+///
+/// On Linux, if `getrandom(2)` fails with `ENOSYS` (old kernels) or `EPERM`
+/// (seccomp filters), this and all later reads fall back to the character
+/// device at `urandom_path`, `/dev/urandom` by default. The fallback ignores
+/// both flags: `/dev/urandom` never blocks, and never draws from the
+/// `/dev/random` pool.
+#[derive(Clone, Debug)]
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
 pub struct RandomStream {
+  nonblock: bool,
+  random:   bool,
+  urandom_path: PathBuf,
+}
+
+impl Default for RandomStream {
+  fn default() -> RandomStream {
+    RandomStream{
+      nonblock: false,
+      random:   false,
+      urandom_path: PathBuf::from("/dev/urandom"),
+    }
+  }
+}
+
+impl RandomStream {
+  pub fn set_urandom_path<P: AsRef<Path>>(&mut self, path: P) {
+    self.urandom_path = path.as_ref().to_owned();
+  }
+
+  pub fn set_nonblock(&mut self, nonblock: bool) {
+    self.nonblock = nonblock;
+  }
+
+  pub fn set_random(&mut self, random: bool) {
+    self.random = random;
+  }
 }
 
 #[cfg(target_os = "linux")]
-impl Read for RandomStream {
-  fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+impl RandomStream {
+  fn read_urandom(&self, buf: &mut [u8]) -> Result<usize, IoError> {
+    let mut file = File::open(&self.urandom_path)?;
+    if !file.metadata()?.file_type().is_char_device() {
+      return Err(IoError::new(IoErrorKind::InvalidData,
+          format!("{} is not a character device", self.urandom_path.display())));
+    }
+    file.read_exact(buf)?;
+    Ok(buf.len())
+  }
+
+  /// The body of `read`, with the syscall and the fallback latch passed in
+  /// so that tests can stand in for a kernel without `getrandom(2)`.
+  fn read_with<F>(&self, buf: &mut [u8], unavailable: &AtomicBool, mut getrandom: F) -> Result<usize, IoError>
+  where F: FnMut(&mut [u8], libc::c_uint) -> Result<usize, IoError> {
     const CHUNK_CAP: usize = 256;
+    let mut flags = 0;
+    if self.nonblock {
+      flags |= libc::GRND_NONBLOCK;
+    }
+    if self.random {
+      flags |= libc::GRND_RANDOM;
+    }
+    if unavailable.load(Ordering::Relaxed) {
+      return self.read_urandom(buf);
+    }
     let buf_len = buf.len();
     let mut o = 0;
     while o < buf_len {
       let chunk_len = min(buf_len - o, CHUNK_CAP);
-      let o2 = o + chunk_len;
-      let mut chunk = &mut buf[o .. o2];
-      let ret = unsafe { libc::getrandom(chunk.as_mut_ptr() as *mut _, chunk_len, 0) };
-      // FIXME: may want to allow returning partial result.
-      if ret < 0 {
-        return Err(IoError::last_os_error());
-      } else {
-        assert_eq!(ret, chunk_len);
+      match getrandom(&mut buf[o .. o + chunk_len], flags) {
+        // `GRND_RANDOM` may return fewer bytes than requested.
+        Ok(n) => o += n,
+        Err(e) => {
+          if e.kind() == IoErrorKind::Interrupted {
+            continue;
+          } else if o > 0 {
+            return Ok(o);
+          }
+          match e.raw_os_error() {
+            Some(libc::ENOSYS) | Some(libc::EPERM) => {
+              unavailable.store(true, Ordering::Relaxed);
+              return self.read_urandom(buf);
+            }
+            _ => {}
+          }
+          return Err(e);
+        }
       }
-      o = o2;
     }
     Ok(buf_len)
   }
 }
 
+#[cfg(target_os = "linux")]
+fn sys_getrandom(chunk: &mut [u8], flags: libc::c_uint) -> Result<usize, IoError> {
+  let ret = unsafe { libc::getrandom(chunk.as_mut_ptr() as *mut _, chunk.len(), flags) };
+  if ret < 0 {
+    return Err(IoError::last_os_error());
+  }
+  Ok(ret as usize)
+}
+
+/// Interrupted calls are retried. If a call fails after some bytes have
+/// already been read, the short count is returned and the error is reported
+/// by the next call.
+#[cfg(target_os = "linux")]
+impl Read for RandomStream {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+    self.read_with(buf, &GETRANDOM_UNAVAILABLE, sys_getrandom)
+  }
+}
+
 #[cfg(target_os = "macos")]
 impl Read for RandomStream {
   fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
@@ -43,15 +173,152 @@ impl Read for RandomStream {
     let mut o = 0;
     while o < buf_len {
       let chunk_len = min(buf_len - o, CHUNK_CAP);
-      let o2 = o + chunk_len;
-      let mut chunk = &mut buf[o .. o2];
+      let chunk = &mut buf[o .. o + chunk_len];
       let ret = unsafe { libc::getentropy(chunk.as_mut_ptr() as *mut _, chunk_len) };
-      // FIXME: may want to allow returning partial result.
       if ret != 0 {
+        if o > 0 {
+          return Ok(o);
+        }
         return Err(IoError::last_os_error());
       }
-      o = o2;
+      o += chunk_len;
     }
     Ok(buf_len)
   }
 }
+
+impl Stream for RandomStream {
+  #[inline]
+  fn next_u8(&mut self) -> u8 {
+    let mut buf = [0; 1];
+    self.fill_bytes(&mut buf);
+    buf[0]
+  }
+
+  fn fill_bytes(&mut self, buf: &mut [u8]) {
+    self.read_exact(buf).unwrap();
+  }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+use super::{RandomStream, fork_epoch, getrandom};
+
+use std::env;
+use std::fs;
+use std::io::{Read, Error as IoError, ErrorKind as IoErrorKind};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Forks, then reads 32 bytes from `rng` in both the parent and the child.
+/// Returns the parent's and the child's output.
+pub(crate) fn fork_and_read<R: Read>(rng: &mut R) -> ([u8; 32], [u8; 32]) {
+  let mut fds = [0; 2];
+  assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+  let pid = unsafe { libc::fork() };
+  assert!(pid >= 0);
+  let mut out = [0; 32];
+  if pid == 0 {
+    // The child must not unwind into the test harness.
+    let status = match rng.read_exact(&mut out) {
+      Ok(_) => {
+        let ret = unsafe { libc::write(fds[1], out.as_ptr() as *const _, out.len()) };
+        if ret == out.len() as isize { 0 } else { 1 }
+      }
+      Err(_) => 1,
+    };
+    unsafe { libc::_exit(status) };
+  }
+  rng.read_exact(&mut out).unwrap();
+  let mut child_out = [0; 32];
+  let ret = unsafe { libc::read(fds[0], child_out.as_mut_ptr() as *mut _, child_out.len()) };
+  let mut status = 0;
+  assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+  unsafe { libc::close(fds[0]) };
+  unsafe { libc::close(fds[1]) };
+  assert_eq!(status, 0);
+  assert_eq!(ret, child_out.len() as isize);
+  (out, child_out)
+}
+
+#[test]
+fn test_fork_epoch_bumped_in_child() {
+  let epoch = fork_epoch();
+  let pid = unsafe { libc::fork() };
+  assert!(pid >= 0);
+  if pid == 0 {
+    unsafe { libc::_exit(if fork_epoch() == epoch + 1 { 0 } else { 1 }) };
+  }
+  let mut status = 0;
+  assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+  assert_eq!(status, 0);
+  assert_eq!(fork_epoch(), epoch);
+}
+
+#[test]
+fn test_getrandom_large_buf() {
+  let mut buf = vec![0; 4096 + 3];
+  getrandom(&mut buf).unwrap();
+  // The chance that a 64-byte tail is all zeros is negligible.
+  assert!(buf[4096 - 61 ..].iter().any(|&x| x != 0));
+}
+
+#[test]
+fn test_random_stream_flags() {
+  let mut stream = RandomStream::default();
+  stream.set_nonblock(true);
+  // The pool is initialized long before the tests run.
+  let mut buf = [0; 300];
+  assert_eq!(stream.read(&mut buf).unwrap(), 300);
+  stream.set_random(true);
+  match stream.read(&mut buf[.. 16]) {
+    Ok(n) => assert!(n > 0 && n <= 16),
+    // Kernels before 5.6 may block on the `/dev/random` pool.
+    Err(e) => assert_eq!(e.kind(), IoErrorKind::WouldBlock),
+  }
+}
+
+#[test]
+fn test_random_stream_urandom_fallback() {
+  let mut stream = RandomStream::default();
+  let mut buf = [0; 64];
+  assert_eq!(stream.read_urandom(&mut buf).unwrap(), 64);
+  assert!(buf.iter().any(|&x| x != 0));
+  // Any character device will do as a fake source.
+  stream.set_urandom_path("/dev/zero");
+  assert_eq!(stream.read_urandom(&mut buf).unwrap(), 64);
+  assert!(buf.iter().all(|&x| x == 0));
+  let path = env::temp_dir().join(format!("rng-fake-urandom-{}", std::process::id()));
+  fs::write(&path, [1; 64]).unwrap();
+  stream.set_urandom_path(&path);
+  let e = stream.read_urandom(&mut buf).unwrap_err();
+  fs::remove_file(&path).unwrap();
+  assert_eq!(e.kind(), IoErrorKind::InvalidData);
+  stream.set_urandom_path("/nonexistent/urandom");
+  assert_eq!(stream.read_urandom(&mut buf).unwrap_err().kind(), IoErrorKind::NotFound);
+}
+
+#[test]
+fn test_random_stream_getrandom_fallback() {
+  let mut stream = RandomStream::default();
+  stream.set_urandom_path("/dev/zero");
+  stream.set_random(true);
+  for &errno in [libc::ENOSYS, libc::EPERM].iter() {
+    let unavailable = AtomicBool::new(false);
+    let mut buf = [1; 300];
+    let n = stream.read_with(&mut buf, &unavailable, |_, _| Err(IoError::from_raw_os_error(errno))).unwrap();
+    assert_eq!(n, 300);
+    assert!(buf.iter().all(|&x| x == 0));
+    assert!(unavailable.load(Ordering::Relaxed));
+    // Later reads go straight to the device.
+    let mut buf = [1; 10];
+    let n = stream.read_with(&mut buf, &unavailable, |_, _| panic!("getrandom called after the fallback")).unwrap();
+    assert_eq!(n, 10);
+    assert!(buf.iter().all(|&x| x == 0));
+  }
+  let unavailable = AtomicBool::new(false);
+  let mut buf = [1; 10];
+  let e = stream.read_with(&mut buf, &unavailable, |_, _| Err(IoError::from_raw_os_error(libc::EINVAL))).unwrap_err();
+  assert_eq!(e.raw_os_error(), Some(libc::EINVAL));
+  assert!(!unavailable.load(Ordering::Relaxed));
+}
+}
@@ -0,0 +1,302 @@
+use crate::{Generator, Buffer32, Buffer64, seek_word, word_stream_position};
+use crate::snapshot::{Snapshot, invalid_snapshot, restore_u64s, save_u64s};
+
+use byteorder::{ReadBytesExt, LittleEndian as LE};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize, Deserializer};
+#[cfg(feature = "serde")]
+use serde::de::{Error as DeError};
+
+use std::io::{Read, Seek, SeekFrom, Error as IoError};
+
+/* pcg32_next, pcg64_next, pcg64_dxsm_next:
+
+PCG Random Number Generation, by Melissa O'Neill <oneill@pcg-random.org>
+and the PCG Project contributors.
+
+Licensed under the Apache License, Version 2.0 or the MIT license, at your
+option. For additional information about the PCG random number generation
+scheme, visit http://www.pcg-random.org/. */
+
+const PCG_DEFAULT_MULTIPLIER_64: u64 = 6364136223846793005;
+const PCG_DEFAULT_MULTIPLIER_128: u128 = 0x2360ed051fc65da44385df649fccf645;
+const PCG_CHEAP_MULTIPLIER_128: u64 = 0xda942042e4dd58b5;
+
+// This is pcg32, i.e. the XSH RR output of a 64-bit LCG, applied to the
+// state before the step.
+pub fn pcg32_next(state: &mut u64, inc: u64) -> u32 {
+  let s = *state;
+  *state = s.wrapping_mul(PCG_DEFAULT_MULTIPLIER_64).wrapping_add(inc);
+  let xsh = (((s >> 18) ^ s) >> 27) as u32;
+  xsh.rotate_right((s >> 59) as u32)
+}
+
+// This is pcg64, i.e. the XSL RR output of a 128-bit LCG, applied to the
+// state after the step.
+pub fn pcg64_next(state: &mut u128, inc: u128) -> u64 {
+  let s = state.wrapping_mul(PCG_DEFAULT_MULTIPLIER_128).wrapping_add(inc);
+  *state = s;
+  let xsl = ((s >> 64) as u64) ^ (s as u64);
+  xsl.rotate_right((s >> 122) as u32)
+}
+
+// This is the DXSM output of a 128-bit LCG with a 64-bit multiplier, applied
+// to the state before the step, as in NumPy's `PCG64DXSM`.
+pub fn pcg64_dxsm_next(state: &mut u128, inc: u128) -> u64 {
+  let s = *state;
+  *state = s.wrapping_mul(PCG_CHEAP_MULTIPLIER_128 as u128).wrapping_add(inc);
+  let mut hi = (s >> 64) as u64;
+  let lo = (s as u64) | 1;
+  hi ^= hi >> 32;
+  hi = hi.wrapping_mul(PCG_CHEAP_MULTIPLIER_128);
+  hi ^= hi >> 48;
+  hi.wrapping_mul(lo)
+}
+
+macro_rules! lcg_advance {
+  ($name:ident, $uint:ty) => {
+    /// Returns the multiplier and increment of the LCG that takes `delta`
+    /// steps of the LCG with multiplier `mul` and increment `inc` at once
+    /// (Brown, "Random Number Generation with Arbitrary Strides", 1994).
+    fn $name(mul: $uint, inc: $uint, delta: $uint) -> ($uint, $uint) {
+      let mut acc_mul: $uint = 1;
+      let mut acc_inc: $uint = 0;
+      let mut cur_mul = mul;
+      let mut cur_inc = inc;
+      let mut d = delta;
+      while d > 0 {
+        if d & 1 != 0 {
+          acc_mul = acc_mul.wrapping_mul(cur_mul);
+          acc_inc = acc_inc.wrapping_mul(cur_mul).wrapping_add(cur_inc);
+        }
+        cur_inc = cur_mul.wrapping_add(1).wrapping_mul(cur_inc);
+        cur_mul = cur_mul.wrapping_mul(cur_mul);
+        d >>= 1;
+      }
+      (acc_mul, acc_inc)
+    }
+  };
+}
+
+lcg_advance!(lcg64_advance, u64);
+lcg_advance!(lcg128_advance, u128);
+
+macro_rules! pcg_generator {
+  ($gen:ident, $stream:ident, $buf:ident, $word:ty, $uint:ty, $nwords:expr, $mul:expr, $next:ident, $advance:ident) => {
+    /// The stream parameter selects one of 2^(n-1) increments, where n is the
+    /// width of the state. Different streams have distinct sequences, but
+    /// they are not guaranteed to be uncorrelated.
+    ///
+    /// Seeks by output word with `seek_word`.
+    #[derive(PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize))]
+    pub struct $gen {
+      state: $uint,
+      inc: $uint,
+      pos: u64,
+    }
+
+    impl $gen {
+      /// Seeds the generator as in the reference `srandom` functions. The
+      /// top bit of `stream` is discarded.
+      pub fn new(seed: $uint, stream: $uint) -> $gen {
+        let inc = (stream << 1) | 1;
+        let mut state: $uint = 0;
+        $next(&mut state, inc);
+        state = state.wrapping_add(seed);
+        $next(&mut state, inc);
+        $gen{state, inc, pos: 0}
+      }
+
+      pub fn stream(&self) -> $uint {
+        self.inc >> 1
+      }
+
+      /// Advances by `delta` steps in O(log delta) time. The period is 2^n,
+      /// so advancing by `k.wrapping_neg()` goes back by `k` steps.
+      pub fn advance(&mut self, delta: $uint) {
+        let (mul, inc) = $advance($mul, self.inc, delta);
+        self.state = self.state.wrapping_mul(mul).wrapping_add(inc);
+        self.pos = self.pos.wrapping_add(delta as u64);
+      }
+
+      /// Rejects states the generator could never reach.
+      fn try_from_parts(state: $uint, inc: $uint, pos: u64) -> Result<$gen, IoError> {
+        if inc & 1 == 0 {
+          return Err(invalid_snapshot(concat!(stringify!($gen), " has an even increment")));
+        }
+        Ok($gen{state, inc, pos})
+      }
+    }
+
+    #[cfg(feature = "serde")]
+    impl<'de> Deserialize<'de> for $gen {
+      fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Unchecked twin of the generator; the shared name keeps the
+        // serialized form the same.
+        #[derive(Deserialize)]
+        struct $gen {
+          state: $uint,
+          inc: $uint,
+          pos: u64,
+        }
+        let raw = $gen::deserialize(deserializer)?;
+        Self::try_from_parts(raw.state, raw.inc, raw.pos).map_err(D::Error::custom)
+      }
+    }
+
+    impl<'r> From<&'r mut dyn Read> for $gen {
+      /// Reads the seed, then the stream.
+      fn from(reader: &'r mut dyn Read) -> $gen {
+        let mut words = [0; 2 * $nwords];
+        for k in 0 .. 2 * $nwords {
+          words[k] = reader.read_u64::<LE>().unwrap();
+        }
+        $gen::new(join_words(&words[.. $nwords]) as $uint, join_words(&words[$nwords ..]) as $uint)
+      }
+    }
+
+    impl Generator<[$word; 1]> for $gen {
+      #[inline]
+      fn next_gen(&mut self, out: &mut [$word; 1]) {
+        out[0] = $next(&mut self.state, self.inc);
+        self.pos = self.pos.wrapping_add(1);
+      }
+    }
+
+    impl Seek for $gen {
+      fn seek(&mut self, pos: SeekFrom) -> Result<u64, IoError> {
+        const WORD_BYTES: u64 = ::std::mem::size_of::<$word>() as u64;
+        let target = seek_word(self.pos, WORD_BYTES, pos)?;
+        self.advance((target as $uint).wrapping_sub(self.pos as $uint));
+        Ok(target * WORD_BYTES)
+      }
+
+      fn stream_position(&mut self) -> Result<u64, IoError> {
+        word_stream_position(self.pos, ::std::mem::size_of::<$word>() as u64)
+      }
+    }
+
+    impl Snapshot for $gen {
+      const TAG: &'static str = stringify!($gen);
+
+      fn save_state(&self, out: &mut Vec<u8>) {
+        save_u64s(out, &split_words(self.state as u128)[.. $nwords]);
+        save_u64s(out, &split_words(self.inc as u128)[.. $nwords]);
+        save_u64s(out, &[self.pos]);
+      }
+
+      fn restore_state(state: &mut &[u8]) -> Result<$gen, IoError> {
+        let mut words = [0; 2 * $nwords + 1];
+        restore_u64s(state, &mut words)?;
+        let inc = join_words(&words[$nwords .. 2 * $nwords]) as $uint;
+        $gen::try_from_parts(join_words(&words[.. $nwords]) as $uint, inc, words[2 * $nwords])
+      }
+    }
+
+    pub type $stream = $buf<$gen, [$word; 1]>;
+  };
+}
+
+fn split_words(x: u128) -> [u64; 2] {
+  [x as u64, (x >> 64) as u64]
+}
+
+fn join_words(words: &[u64]) -> u128 {
+  words.iter().rev().fold(0, |x, &w| (x << 64) | w as u128)
+}
+
+pcg_generator!(Pcg32Generator, Pcg32Stream, Buffer32, u32, u64, 1, PCG_DEFAULT_MULTIPLIER_64, pcg32_next, lcg64_advance);
+pcg_generator!(Pcg64Generator, Pcg64Stream, Buffer64, u64, u128, 2, PCG_DEFAULT_MULTIPLIER_128, pcg64_next, lcg128_advance);
+pcg_generator!(Pcg64DxsmGenerator, Pcg64DxsmStream, Buffer64, u64, u128, 2, PCG_CHEAP_MULTIPLIER_128 as u128, pcg64_dxsm_next, lcg128_advance);
+
+impl_stream_gen32!(Pcg32Generator);
+impl_stream_gen64!(Pcg64Generator);
+impl_stream_gen64!(Pcg64DxsmGenerator);
+
+#[cfg(test)]
+mod tests {
+use super::{Pcg32Generator, Pcg32Stream, Pcg64Generator, Pcg64Stream, Pcg64DxsmGenerator};
+use crate::{Stream};
+
+use std::io::{Read, Seek, SeekFrom, ErrorKind as IoErrorKind};
+
+#[test]
+fn test_pcg_reference() {
+  // From the test suites of the reference C and C++ implementations.
+  let mut gen = Pcg32Generator::new(42, 54);
+  for &x in [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e].iter() {
+    assert_eq!(gen.next_u32(), x);
+  }
+  let mut gen = Pcg64Generator::new(42, 54);
+  for &x in [0x86b1da1d72062b68, 0x1304aa46c9853d39, 0xa3670e9e0dd50358, 0xf9090e529a7dae00, 0xc85b9fd837996f2c, 0x606121f8e3919196].iter() {
+    assert_eq!(gen.next_u64(), x);
+  }
+  let mut gen = Pcg64DxsmGenerator::new(42, 54);
+  for &x in [17331114245835578256, 10267467544499227306, 9726600296081716989, 10165951391103677450, 12131334649314727261, 10134094537930450875].iter() {
+    assert_eq!(gen.next_u64(), x);
+  }
+}
+
+#[test]
+fn test_pcg_numpy() {
+  // NumPy's `PCG64(0xdeadbeaf)` and `PCG64DXSM(0xdeadbeaf)`, which seed
+  // with the output of `SeedSequence(0xdeadbeaf)`.
+  let seed = 0x50312a63d516e24a4063bc8bca44dc77;
+  let stream = 0xb780f022ab4ec9e22056086e8249885a;
+  let mut gen = Pcg64Generator::new(seed, stream);
+  for &x in [0x60d24054e17a0698, 0xd5e79d89856e4f12, 0xd254972fe64bd782, 0xf1e3072a53c72571].iter() {
+    assert_eq!(gen.next_u64(), x);
+  }
+  let mut gen = Pcg64DxsmGenerator::new(seed, stream);
+  for &x in [0x353db1139412b43b, 0x207a578fed91f8fa, 0xe716b05be6eb4aac, 0x06d6d252bb51470c].iter() {
+    assert_eq!(gen.next_u64(), x);
+  }
+}
+
+#[test]
+fn test_pcg_advance() {
+  let mut gen = Pcg32Generator::new(42, 54);
+  let mut expected = Pcg32Generator::new(42, 54);
+  for _ in 0 .. 20 {
+    expected.next_u32();
+  }
+  gen.advance(20);
+  assert_eq!(gen, expected);
+  gen.advance(5_u64.wrapping_neg());
+  gen.advance(5);
+  assert_eq!(gen, expected);
+  let mut gen = Pcg64Generator::new(1234, 567);
+  let mut expected = Pcg64Generator::new(1234, 567);
+  for _ in 0 .. 100 {
+    expected.next_u64();
+  }
+  gen.advance(100);
+  assert_eq!(gen, expected);
+  let mut gen = Pcg64DxsmGenerator::new(1234, 567);
+  gen.advance(1 << 100);
+  gen.advance((1_u128 << 100).wrapping_neg());
+  assert_eq!(gen.stream(), 567);
+  assert_eq!(gen.next_u64(), Pcg64DxsmGenerator::new(1234, 567).next_u64());
+}
+
+#[test]
+fn test_pcg_stream_seek() {
+  let mut stream = Pcg32Stream::new(Pcg32Generator::new(42, 54));
+  let mut expected = [0; 100];
+  stream.read_exact(&mut expected).unwrap();
+  let mut buf = [0; 30];
+  assert_eq!(stream.seek(SeekFrom::Start(13)).unwrap(), 13);
+  stream.read_exact(&mut buf).unwrap();
+  assert_eq!(&buf[..], &expected[13 .. 43]);
+  assert_eq!(stream.seek(SeekFrom::Current(30)).unwrap(), 73);
+  stream.read_exact(&mut buf[.. 27]).unwrap();
+  assert_eq!(&buf[.. 27], &expected[73 ..]);
+  let mut stream = Pcg64Stream::new(Pcg64Generator::new(42, 54));
+  stream.read_exact(&mut expected).unwrap();
+  assert_eq!(stream.seek(SeekFrom::Current(-95)).unwrap(), 5);
+  stream.read_exact(&mut buf).unwrap();
+  assert_eq!(&buf[..], &expected[5 .. 35]);
+  assert_eq!(stream.seek(SeekFrom::End(-8)).unwrap_err().kind(), IoErrorKind::InvalidInput);
+}
+}
@@ -0,0 +1,242 @@
+use crate::{zero_volatile};
+
+use byteorder::{ByteOrder, LittleEndian as LE};
+
+/* Poly1305State:
+
+Based on poly1305-donna (32-bit limbs), written by Andrew Moon and released
+into the public domain or under the MIT license.
+
+See <https://github.com/floodyberry/poly1305-donna>. */
+
+pub const POLY1305_KEY_LEN: usize = 32;
+pub const POLY1305_TAG_LEN: usize = 16;
+
+/// Incremental Poly1305 one-time authenticator (RFC 8439, section 2.5).
+/// A key must never be used to authenticate more than one message.
+pub struct Poly1305State {
+  r:    [u32; 5],
+  h:    [u32; 5],
+  pad:  [u32; 4],
+  buf:  [u8; 16],
+  buf_len: usize,
+}
+
+impl Poly1305State {
+  pub fn new<K: AsRef<[u8]>>(key_buf: K) -> Poly1305State {
+    let key = key_buf.as_ref();
+    assert_eq!(key.len(), POLY1305_KEY_LEN);
+    let r = [
+        (LE::read_u32(&key[0 .. 4])) & 0x3ffffff,
+        (LE::read_u32(&key[3 .. 7]) >> 2) & 0x3ffff03,
+        (LE::read_u32(&key[6 .. 10]) >> 4) & 0x3ffc0ff,
+        (LE::read_u32(&key[9 .. 13]) >> 6) & 0x3f03fff,
+        (LE::read_u32(&key[12 .. 16]) >> 8) & 0x00fffff,
+    ];
+    let pad = [
+        LE::read_u32(&key[16 .. 20]),
+        LE::read_u32(&key[20 .. 24]),
+        LE::read_u32(&key[24 .. 28]),
+        LE::read_u32(&key[28 .. 32]),
+    ];
+    Poly1305State{r, h: [0; 5], pad, buf: [0; 16], buf_len: 0}
+  }
+
+  fn block(&mut self, m: &[u8; 16], hibit: u32) {
+    let [r0, r1, r2, r3, r4] = self.r;
+    let (r0, r1, r2, r3, r4) = (r0 as u64, r1 as u64, r2 as u64, r3 as u64, r4 as u64);
+    let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+    let [mut h0, mut h1, mut h2, mut h3, mut h4] = self.h;
+    h0 += (LE::read_u32(&m[0 .. 4])) & 0x3ffffff;
+    h1 += (LE::read_u32(&m[3 .. 7]) >> 2) & 0x3ffffff;
+    h2 += (LE::read_u32(&m[6 .. 10]) >> 4) & 0x3ffffff;
+    h3 += (LE::read_u32(&m[9 .. 13]) >> 6) & 0x3ffffff;
+    h4 += (LE::read_u32(&m[12 .. 16]) >> 8) | hibit;
+    let (h0, h1, h2, h3, h4) = (h0 as u64, h1 as u64, h2 as u64, h3 as u64, h4 as u64);
+    let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+    let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+    let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+    let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+    let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+    let mut c = d0 >> 26;
+    let mut h0 = (d0 & 0x3ffffff) as u32;
+    d1 += c; c = d1 >> 26; let mut h1 = (d1 & 0x3ffffff) as u32;
+    d2 += c; c = d2 >> 26; let h2 = (d2 & 0x3ffffff) as u32;
+    d3 += c; c = d3 >> 26; let h3 = (d3 & 0x3ffffff) as u32;
+    d4 += c; c = d4 >> 26; let h4 = (d4 & 0x3ffffff) as u32;
+    h0 += (c * 5) as u32;
+    let c = h0 >> 26;
+    h0 &= 0x3ffffff;
+    h1 += c;
+    self.h = [h0, h1, h2, h3, h4];
+  }
+
+  pub fn update(&mut self, mut data: &[u8]) {
+    if self.buf_len > 0 {
+      let n = data.len().min(16 - self.buf_len);
+      self.buf[self.buf_len .. self.buf_len + n].copy_from_slice(&data[ .. n]);
+      self.buf_len += n;
+      data = &data[n .. ];
+      if self.buf_len < 16 {
+        return;
+      }
+      let m = self.buf;
+      self.block(&m, 1 << 24);
+      self.buf_len = 0;
+    }
+    while data.len() >= 16 {
+      let mut m = [0; 16];
+      m.copy_from_slice(&data[ .. 16]);
+      self.block(&m, 1 << 24);
+      data = &data[16 .. ];
+    }
+    self.buf[ .. data.len()].copy_from_slice(data);
+    self.buf_len = data.len();
+  }
+
+  /// Pads the message with zeros to a multiple of 16 bytes, as used by the
+  /// AEAD construction.
+  pub fn pad16(&mut self) {
+    if self.buf_len > 0 {
+      let zeros = [0; 16];
+      let n = 16 - self.buf_len;
+      self.update(&zeros[ .. n]);
+    }
+  }
+
+  pub fn finalize(mut self) -> [u8; POLY1305_TAG_LEN] {
+    if self.buf_len > 0 {
+      let mut m = [0; 16];
+      m[ .. self.buf_len].copy_from_slice(&self.buf[ .. self.buf_len]);
+      m[self.buf_len] = 1;
+      self.block(&m, 0);
+    }
+    let [mut h0, mut h1, mut h2, mut h3, mut h4] = self.h;
+    let mut c = h1 >> 26; h1 &= 0x3ffffff;
+    h2 += c; c = h2 >> 26; h2 &= 0x3ffffff;
+    h3 += c; c = h3 >> 26; h3 &= 0x3ffffff;
+    h4 += c; c = h4 >> 26; h4 &= 0x3ffffff;
+    h0 += c * 5; c = h0 >> 26; h0 &= 0x3ffffff;
+    h1 += c;
+    // Compute h + -p and select it if h >= p, in constant time.
+    let mut g0 = h0.wrapping_add(5); c = g0 >> 26; g0 &= 0x3ffffff;
+    let mut g1 = h1.wrapping_add(c); c = g1 >> 26; g1 &= 0x3ffffff;
+    let mut g2 = h2.wrapping_add(c); c = g2 >> 26; g2 &= 0x3ffffff;
+    let mut g3 = h3.wrapping_add(c); c = g3 >> 26; g3 &= 0x3ffffff;
+    let mut g4 = h4.wrapping_add(c).wrapping_sub(1 << 26);
+    let mut mask = (g4 >> 31).wrapping_sub(1);
+    g0 &= mask; g1 &= mask; g2 &= mask; g3 &= mask; g4 &= mask;
+    mask = !mask;
+    h0 = (h0 & mask) | g0;
+    h1 = (h1 & mask) | g1;
+    h2 = (h2 & mask) | g2;
+    h3 = (h3 & mask) | g3;
+    h4 = (h4 & mask) | g4;
+    let h0 = h0 | (h1 << 26);
+    let h1 = (h1 >> 6) | (h2 << 20);
+    let h2 = (h2 >> 12) | (h3 << 14);
+    let h3 = (h3 >> 18) | (h4 << 8);
+    let mut tag = [0; POLY1305_TAG_LEN];
+    let mut f = h0 as u64 + self.pad[0] as u64;
+    LE::write_u32(&mut tag[0 .. 4], f as u32);
+    f = h1 as u64 + self.pad[1] as u64 + (f >> 32);
+    LE::write_u32(&mut tag[4 .. 8], f as u32);
+    f = h2 as u64 + self.pad[2] as u64 + (f >> 32);
+    LE::write_u32(&mut tag[8 .. 12], f as u32);
+    f = h3 as u64 + self.pad[3] as u64 + (f >> 32);
+    LE::write_u32(&mut tag[12 .. 16], f as u32);
+    tag
+  }
+}
+
+impl Drop for Poly1305State {
+  fn drop(&mut self) {
+    zero_volatile(&mut self.r);
+    zero_volatile(&mut self.h);
+    zero_volatile(&mut self.pad);
+    zero_volatile(&mut self.buf);
+  }
+}
+
+pub fn poly1305<K: AsRef<[u8]>>(key_buf: K, msg: &[u8]) -> [u8; POLY1305_TAG_LEN] {
+  let mut state = Poly1305State::new(key_buf);
+  state.update(msg);
+  state.finalize()
+}
+
+/// Compares two tags without branching on their contents.
+pub fn tag_eq(lhs: &[u8], rhs: &[u8]) -> bool {
+  if lhs.len() != rhs.len() {
+    return false;
+  }
+  let mut acc = 0;
+  for (&x, &y) in lhs.iter().zip(rhs.iter()) {
+    acc |= x ^ y;
+  }
+  acc == 0
+}
+
+#[cfg(test)]
+mod tests {
+use super::{Poly1305State, poly1305};
+use crate::{hex_bytes};
+
+const IETF_CONTRIBUTION: &[u8] = b"Any submission to the IETF intended by the Contributor for publication as all or part of an IETF Internet-Draft or RFC and any statement made within the context of an IETF activity is considered an \"IETF Contribution\". Such statements include oral statements in IETF sessions, as well as written and electronic communications made at any time or place, which are addressed to";
+
+fn check(key_hex: &str, msg: &[u8], tag_hex: &str) {
+  let key = hex_bytes(key_hex);
+  assert_eq!(&poly1305(&key, msg)[..], &hex_bytes(tag_hex)[..]);
+  // Same message fed in uneven pieces.
+  let mut state = Poly1305State::new(&key);
+  for chunk in msg.chunks(7) {
+    state.update(chunk);
+  }
+  assert_eq!(&state.finalize()[..], &hex_bytes(tag_hex)[..]);
+}
+
+#[test]
+fn test_poly1305_rfc8439_2_5_2() {
+  check("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b",
+      b"Cryptographic Forum Research Group",
+      "a8061dc1305136c6c22b8baf0c0127a9");
+}
+
+#[test]
+fn test_poly1305_rfc8439_a_3() {
+  check("0000000000000000000000000000000000000000000000000000000000000000",
+      &[0; 64],
+      "00000000000000000000000000000000");
+  check("0000000000000000000000000000000036e5f6b5c5e06070f0efca96227a863e",
+      IETF_CONTRIBUTION,
+      "36e5f6b5c5e06070f0efca96227a863e");
+  check("36e5f6b5c5e06070f0efca96227a863e00000000000000000000000000000000",
+      IETF_CONTRIBUTION,
+      "f3477e7cd95417af89a6b8794c310cf0");
+  check("1c9240a5eb55d38af333888604f6b5f0473917c1402b80099dca5cbc207075c0",
+      b"'Twas brillig, and the slithy toves\nDid gyre and gimble in the wabe:\nAll mimsy were the borogoves,\nAnd the mome raths outgrabe.",
+      "4541669a7eaaee61e708dc7cbcc5eb62");
+  check("0200000000000000000000000000000000000000000000000000000000000000",
+      &hex_bytes("ffffffffffffffffffffffffffffffff"),
+      "03000000000000000000000000000000");
+  check("02000000000000000000000000000000ffffffffffffffffffffffffffffffff",
+      &hex_bytes("02000000000000000000000000000000"),
+      "03000000000000000000000000000000");
+  check("0100000000000000000000000000000000000000000000000000000000000000",
+      &hex_bytes("fffffffffffffffffffffffffffffffff0ffffffffffffffffffffffffffffff11000000000000000000000000000000"),
+      "05000000000000000000000000000000");
+  check("0100000000000000000000000000000000000000000000000000000000000000",
+      &hex_bytes("fffffffffffffffffffffffffffffffffbfefefefefefefefefefefefefefefe01010101010101010101010101010101"),
+      "00000000000000000000000000000000");
+  check("0200000000000000000000000000000000000000000000000000000000000000",
+      &hex_bytes("fdffffffffffffffffffffffffffffff"),
+      "faffffffffffffffffffffffffffffff");
+  let msg = hex_bytes("e33594d7505e43b900000000000000003394d7505e4379cd010000000000000000000000000000000000000000000000\
+                       01000000000000000000000000000000");
+  check("0100000000000000040000000000000000000000000000000000000000000000",
+      &msg,
+      "14000000000000005500000000000000");
+  check("0100000000000000040000000000000000000000000000000000000000000000",
+      &msg[.. 48],
+      "13000000000000000000000000000000");
+}
+}
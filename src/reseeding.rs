@@ -0,0 +1,165 @@
+use crate::{Reseed};
+use crate::os::{RandomStream, fork_epoch};
+
+use std::io::{Read, Error as IoError};
+use std::process::{id as process_id};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_RESEED_BYTES: u64 = 1 << 20;
+
+/// Periodically reseeds the wrapped stream from `os::RandomStream`: once
+/// `byte_threshold` bytes have been read or `time_threshold` has elapsed
+/// since the previous reseed, and whenever a `fork` was detected, either via
+/// `os::fork_epoch` or a changed process id, so that parent and child do not
+/// share output.
+pub struct Reseeding<G> {
+  inner:  G,
+  byte_threshold: Option<u64>,
+  time_threshold: Option<Duration>,
+  bytes:  u64,
+  last:   Instant,
+  pid:    u32,
+  epoch:  usize,
+}
+
+impl<G: Read + Reseed> Reseeding<G> {
+  /// Wraps an already seeded stream; the first reseed happens after
+  /// `DEFAULT_RESEED_BYTES` bytes.
+  pub fn new(inner: G) -> Reseeding<G> {
+    Reseeding{
+      inner,
+      byte_threshold: Some(DEFAULT_RESEED_BYTES),
+      time_threshold: None,
+      bytes:  0,
+      last:   Instant::now(),
+      pid:    process_id(),
+      epoch:  fork_epoch(),
+    }
+  }
+
+  pub fn set_byte_threshold(&mut self, threshold: Option<u64>) {
+    self.byte_threshold = threshold;
+  }
+
+  pub fn set_time_threshold(&mut self, threshold: Option<Duration>) {
+    self.time_threshold = threshold;
+  }
+
+  pub fn get_ref(&self) -> &G {
+    &self.inner
+  }
+
+  pub fn into_inner(self) -> G {
+    self.inner
+  }
+
+  pub fn reseed(&mut self) -> Result<(), IoError> {
+    self.inner.reseed(RandomStream::default())?;
+    self.bytes = 0;
+    self.last = Instant::now();
+    self.pid = process_id();
+    self.epoch = fork_epoch();
+    Ok(())
+  }
+
+  fn needs_reseed(&self) -> bool {
+    if self.epoch != fork_epoch() || self.pid != process_id() {
+      return true;
+    }
+    if let Some(threshold) = self.byte_threshold {
+      if self.bytes >= threshold {
+        return true;
+      }
+    }
+    if let Some(threshold) = self.time_threshold {
+      if self.last.elapsed() >= threshold {
+        return true;
+      }
+    }
+    false
+  }
+}
+
+impl<G: Read + Reseed> Read for Reseeding<G> {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+    if self.needs_reseed() {
+      self.reseed()?;
+    }
+    let mut len = buf.len();
+    if let Some(threshold) = self.byte_threshold {
+      len = len.min(threshold.saturating_sub(self.bytes).max(1) as usize);
+    }
+    let n = self.inner.read(&mut buf[ .. len])?;
+    self.bytes += n as u64;
+    Ok(n)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+use super::{Reseeding};
+use crate::chacha20::{ChaCha20Generator, ChaCha20Stream, XChaCha20Generator, XChaCha20Stream};
+use crate::os::tests::{fork_and_read};
+
+use std::io::{Read};
+use std::time::{Duration};
+
+fn test_stream() -> ChaCha20Stream {
+  ChaCha20Stream::new(ChaCha20Generator::from_parts(b"expand 32-byte k", [9; 32], 0, 0))
+}
+
+#[test]
+fn test_reseeding_byte_threshold() {
+  let mut expected = [0; 200];
+  test_stream().read_exact(&mut expected).unwrap();
+  let mut rng = Reseeding::new(test_stream());
+  rng.set_byte_threshold(Some(100));
+  let mut out = [0; 200];
+  rng.read_exact(&mut out).unwrap();
+  assert_eq!(&out[.. 100], &expected[.. 100]);
+  assert_ne!(&out[100 ..], &expected[100 ..]);
+  assert_eq!(rng.bytes, 100);
+  // Reseeding keeps the counter and drops the rest of the buffered block.
+  assert_eq!(rng.get_ref().gen.block_counter(), 4);
+}
+
+#[test]
+fn test_reseeding_time_threshold() {
+  let mut expected = [0; 64];
+  test_stream().read_exact(&mut expected).unwrap();
+  let mut rng = Reseeding::new(test_stream());
+  rng.set_byte_threshold(None);
+  rng.set_time_threshold(Some(Duration::from_secs(3600)));
+  let mut out = [0; 32];
+  rng.read_exact(&mut out).unwrap();
+  assert_eq!(&out[..], &expected[.. 32]);
+  rng.set_time_threshold(Some(Duration::from_secs(0)));
+  rng.read_exact(&mut out).unwrap();
+  assert_ne!(&out[..], &expected[32 ..]);
+}
+
+#[test]
+fn test_reseeding_pid_change() {
+  let mut expected = [0; 64];
+  XChaCha20Stream::new(XChaCha20Generator::from_parts([9; 32], [1; 24], 0)).read_exact(&mut expected).unwrap();
+  let mut rng = Reseeding::new(XChaCha20Stream::new(XChaCha20Generator::from_parts([9; 32], [1; 24], 0)));
+  let mut out = [0; 32];
+  rng.read_exact(&mut out).unwrap();
+  assert_eq!(&out[..], &expected[.. 32]);
+  // Pretend that we are now running in a forked child.
+  rng.pid = rng.pid.wrapping_add(1);
+  rng.read_exact(&mut out).unwrap();
+  assert_ne!(&out[..], &expected[32 ..]);
+  assert_eq!(rng.pid, std::process::id());
+}
+
+#[test]
+fn test_reseeding_fork_diverges() {
+  let mut rng = Reseeding::new(test_stream());
+  rng.set_byte_threshold(None);
+  let mut out = [0; 32];
+  rng.read_exact(&mut out).unwrap();
+  let (parent, child) = fork_and_read(&mut rng);
+  assert_ne!(parent, child);
+}
+}
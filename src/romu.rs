@@ -1,4 +1,12 @@
 use crate::{Generator, Buffer32};
+use crate::snapshot::{Snapshot, invalid_snapshot, restore_u32s, save_u32s};
+
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+#[cfg(feature = "serde")]
+use std::convert::{TryFrom};
+use std::io::{Error as IoError};
 
 /* romu32x4_next:
 
@@ -25,10 +33,40 @@ pub fn romu32x4_next(state: &mut [u32; 4]) -> u32 {
   xp
 }
 
+#[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "Romu32x4State"))]
 pub struct Romu32x4Generator {
   state: [u32; 4],
 }
 
+/// Unchecked form of `Romu32x4Generator`, for deserialization.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "Romu32x4Generator")]
+struct Romu32x4State {
+  state: [u32; 4],
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<Romu32x4State> for Romu32x4Generator {
+  type Error = IoError;
+
+  fn try_from(raw: Romu32x4State) -> Result<Romu32x4Generator, IoError> {
+    Romu32x4Generator::try_from_state(raw.state)
+  }
+}
+
+impl Romu32x4Generator {
+  /// Rejects the all-zero state, which the generator can never leave.
+  fn try_from_state(state: [u32; 4]) -> Result<Romu32x4Generator, IoError> {
+    if state == [0; 4] {
+      return Err(invalid_snapshot("Romu32x4Generator has an all-zero state"));
+    }
+    Ok(Romu32x4Generator{state})
+  }
+}
+
 impl From<[u32; 4]> for Romu32x4Generator {
   fn from(state: [u32; 4]) -> Romu32x4Generator {
     Romu32x4Generator{state}
@@ -42,4 +80,20 @@ impl Generator<[u32; 1]> for Romu32x4Generator {
   }
 }
 
+impl Snapshot for Romu32x4Generator {
+  const TAG: &'static str = "Romu32x4Generator";
+
+  fn save_state(&self, out: &mut Vec<u8>) {
+    save_u32s(out, &self.state);
+  }
+
+  fn restore_state(state: &mut &[u8]) -> Result<Romu32x4Generator, IoError> {
+    let mut s = [0; 4];
+    restore_u32s(state, &mut s)?;
+    Romu32x4Generator::try_from_state(s)
+  }
+}
+
+impl_stream_gen32!(Romu32x4Generator);
+
 pub type Romu32x4Stream = Buffer32<Romu32x4Generator, [u32; 1]>;
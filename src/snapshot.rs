@@ -0,0 +1,275 @@
+use crate::{Buffer32, Buffer64};
+
+use byteorder::{ReadBytesExt, LittleEndian as LE};
+
+use std::io::{Read, Write, Error as IoError, ErrorKind as IoErrorKind};
+
+/* Snapshot format, all integers little endian:
+
+    magic           4 bytes, "RNGS"
+    version         u8, currently 1
+    tag length      u8
+    tag             the algorithm tag, e.g. "ChaCha20Generator"
+    payload length  u32
+    payload         the state, as written by `save_state`
+    checksum        u64, FNV-1a of all preceding bytes
+
+A buffered stream's payload is the snapshot of its generator, followed by
+the buffer length in words, the buffered words and the byte cursor. */
+
+pub const SNAPSHOT_MAGIC: &[u8; 4] = b"RNGS";
+pub const SNAPSHOT_VERSION: u8 = 1;
+
+const MAX_PAYLOAD_LEN: u32 = 1 << 20;
+
+fn fnv1a64(buf: &[u8]) -> u64 {
+  let mut h = 0xcbf29ce484222325_u64;
+  for &x in buf.iter() {
+    h ^= x as u64;
+    h = h.wrapping_mul(0x100000001b3);
+  }
+  h
+}
+
+pub(crate) fn invalid_snapshot<S: Into<String>>(msg: S) -> IoError {
+  IoError::new(IoErrorKind::InvalidData, msg.into())
+}
+
+/// Versioned binary checkpoints of generator and stream state. Restoring a
+/// snapshot yields a value that continues exactly where the saved one was.
+///
+/// Snapshots of cryptographic generators contain the key in the clear.
+pub trait Snapshot: Sized {
+  /// Identifies the algorithm; restoring checks it against the snapshot.
+  const TAG: &'static str;
+
+  fn save_state(&self, out: &mut Vec<u8>);
+
+  /// Parses the payload written by `save_state`, advancing `state` past it.
+  /// Fails on states the generator could never reach.
+  fn restore_state(state: &mut &[u8]) -> Result<Self, IoError>;
+
+  fn save<W: Write>(&self, mut w: W) -> Result<(), IoError> {
+    let mut payload = Vec::new();
+    self.save_state(&mut payload);
+    w.write_all(&encode_snapshot(Self::TAG, &payload))
+  }
+
+  fn restore<R: Read>(mut r: R) -> Result<Self, IoError> {
+    let payload = decode_snapshot(&mut r, Self::TAG)?;
+    let mut state = &payload[..];
+    let x = Self::restore_state(&mut state)?;
+    if !state.is_empty() {
+      return Err(invalid_snapshot(format!("{} snapshot has {} trailing bytes", Self::TAG, state.len())));
+    }
+    Ok(x)
+  }
+}
+
+pub(crate) fn encode_snapshot(tag: &str, payload: &[u8]) -> Vec<u8> {
+  assert!(tag.len() <= u8::MAX as usize);
+  assert!(payload.len() <= MAX_PAYLOAD_LEN as usize);
+  let mut buf = Vec::with_capacity(payload.len() + tag.len() + 18);
+  buf.extend_from_slice(SNAPSHOT_MAGIC);
+  buf.push(SNAPSHOT_VERSION);
+  buf.push(tag.len() as u8);
+  buf.extend_from_slice(tag.as_bytes());
+  buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+  buf.extend_from_slice(payload);
+  let checksum = fnv1a64(&buf);
+  buf.extend_from_slice(&checksum.to_le_bytes());
+  buf
+}
+
+fn decode_snapshot<R: Read>(r: &mut R, tag: &str) -> Result<Vec<u8>, IoError> {
+  let mut header = [0; 6];
+  r.read_exact(&mut header)?;
+  if &header[ .. 4] != SNAPSHOT_MAGIC {
+    return Err(invalid_snapshot("not an rng snapshot"));
+  }
+  if header[4] != SNAPSHOT_VERSION {
+    return Err(invalid_snapshot(format!("unsupported snapshot version {}", header[4])));
+  }
+  let mut buf = header.to_vec();
+  let mut saved_tag = vec![0; header[5] as usize];
+  r.read_exact(&mut saved_tag)?;
+  buf.extend_from_slice(&saved_tag);
+  let payload_len = r.read_u32::<LE>()?;
+  if payload_len > MAX_PAYLOAD_LEN {
+    return Err(invalid_snapshot(format!("snapshot payload length {} is too large", payload_len)));
+  }
+  buf.extend_from_slice(&payload_len.to_le_bytes());
+  let mut payload = vec![0; payload_len as usize];
+  r.read_exact(&mut payload)?;
+  buf.extend_from_slice(&payload);
+  if r.read_u64::<LE>()? != fnv1a64(&buf) {
+    return Err(invalid_snapshot("snapshot checksum mismatch"));
+  }
+  if &saved_tag[..] != tag.as_bytes() {
+    return Err(invalid_snapshot(format!("snapshot is for {}, not {}",
+        String::from_utf8_lossy(&saved_tag), tag)));
+  }
+  Ok(payload)
+}
+
+pub(crate) fn save_u32s(out: &mut Vec<u8>, xs: &[u32]) {
+  for &x in xs.iter() {
+    out.extend_from_slice(&x.to_le_bytes());
+  }
+}
+
+pub(crate) fn save_u64s(out: &mut Vec<u8>, xs: &[u64]) {
+  for &x in xs.iter() {
+    out.extend_from_slice(&x.to_le_bytes());
+  }
+}
+
+pub(crate) fn restore_u32s(state: &mut &[u8], xs: &mut [u32]) -> Result<(), IoError> {
+  state.read_u32_into::<LE>(xs)
+}
+
+pub(crate) fn restore_u64s(state: &mut &[u8], xs: &mut [u64]) -> Result<(), IoError> {
+  state.read_u64_into::<LE>(xs)
+}
+
+macro_rules! buffer_snapshot {
+  ($buf:ident, $tag:expr, $word:tt, $save:ident, $restore:ident) => {
+    impl<R: Snapshot, U: AsRef<[$word]> + AsMut<[$word]> + Default> Snapshot for $buf<R, U> {
+      const TAG: &'static str = $tag;
+
+      fn save_state(&self, out: &mut Vec<u8>) {
+        let mut gen = Vec::new();
+        self.gen.save(&mut gen).unwrap();
+        out.extend_from_slice(&gen);
+        let ubuf = self.ubuf.as_ref();
+        out.extend_from_slice(&(ubuf.len() as u32).to_le_bytes());
+        $save(out, ubuf);
+        out.extend_from_slice(&(self.cur as u32).to_le_bytes());
+      }
+
+      fn restore_state(state: &mut &[u8]) -> Result<$buf<R, U>, IoError> {
+        let gen = R::restore(&mut *state)?;
+        let mut ubuf = U::default();
+        let len = state.read_u32::<LE>()? as usize;
+        if len != ubuf.as_ref().len() {
+          return Err(invalid_snapshot(format!("{} snapshot has {} buffered words, expected {}",
+              $tag, len, ubuf.as_ref().len())));
+        }
+        $restore(state, ubuf.as_mut())?;
+        let cur = state.read_u32::<LE>()? as usize;
+        $buf::try_from_parts(gen, ubuf, cur)
+      }
+    }
+  };
+}
+
+buffer_snapshot!(Buffer32, "Buffer32", u32, save_u32s, restore_u32s);
+buffer_snapshot!(Buffer64, "Buffer64", u64, save_u64s, restore_u64s);
+
+#[cfg(test)]
+mod tests {
+use super::{Snapshot, encode_snapshot};
+use crate::{Stream};
+use crate::chacha20::*;
+use crate::pcg::{Pcg32Generator, Pcg32Stream, Pcg64DxsmGenerator, Pcg64Generator};
+use crate::romu::{Romu32x4Generator, Romu32x4Stream};
+use crate::splitmix::{Splitmix64Generator, Splitmix64Stream};
+use crate::xorshift::*;
+
+use std::io::{Read, ErrorKind as IoErrorKind};
+
+fn check_round_trip<S: Snapshot + Read>(mut stream: S) {
+  let mut buf = [0; 77];
+  stream.read_exact(&mut buf).unwrap();
+  let mut snapshot = Vec::new();
+  stream.save(&mut snapshot).unwrap();
+  let mut restored = S::restore(&snapshot[..]).unwrap();
+  let mut expected = [0; 1000];
+  stream.read_exact(&mut expected).unwrap();
+  restored.read_exact(&mut buf).unwrap();
+  assert_eq!(&buf[..], &expected[.. 77]);
+  let mut rest = [0; 923];
+  restored.read_exact(&mut rest).unwrap();
+  assert_eq!(&rest[..], &expected[77 ..]);
+}
+
+fn check_gen_round_trip<G: Snapshot + Stream>(mut gen: G) {
+  gen.next_u64();
+  let mut snapshot = Vec::new();
+  gen.save(&mut snapshot).unwrap();
+  let mut restored = G::restore(&snapshot[..]).unwrap();
+  for _ in 0 .. 100 {
+    assert_eq!(restored.next_u64(), gen.next_u64());
+  }
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+  let key = [7; 32];
+  check_round_trip(ChaCha8Stream::new(ChaCha8Generator::from_parts(b"expand 32-byte k", key, 1, 2)));
+  check_round_trip(ChaCha12Stream::new(ChaCha12Generator::from_parts(b"expand 32-byte k", key, 1, 2)));
+  check_round_trip(ChaCha20Stream::new(ChaCha20Generator::from_parts(b"expand 32-byte k", key, 1, 2)));
+  check_round_trip(ChaCha20WideStream::new(ChaCha20Generator::from_parts(b"expand 32-byte k", key, 1, 2)));
+  check_round_trip(ChaCha20IetfStream::new(ChaCha20IetfGenerator::from_parts(key, [3; 12], 2)));
+  check_round_trip(XChaCha20Stream::new(XChaCha20Generator::from_parts(key, [3; 24], 2)));
+  check_round_trip(Pcg32Stream::new(Pcg32Generator::new(42, 54)));
+  check_round_trip(Romu32x4Stream::new(Romu32x4Generator::from([1, 2, 3, 4])));
+  check_round_trip(Splitmix64Stream::new(Splitmix64Generator::from(5)));
+  check_round_trip(Xoroshiro1024Stream::new(Xoroshiro1024Generator::from([9; 16])));
+  check_round_trip(Xoshiro256plusplusStream::new(Xoshiro256plusplusGenerator::from([1, 2, 3, 4])));
+  check_gen_round_trip(Pcg32Generator::new(42, 54));
+  check_gen_round_trip(Pcg64Generator::new(42, 54));
+  check_gen_round_trip(Pcg64DxsmGenerator::new(42, 54));
+  check_gen_round_trip(Romu32x4Generator::from([1, 2, 3, 4]));
+  check_gen_round_trip(Splitmix64Generator::from(5));
+  check_gen_round_trip(Xoroshiro1024Generator::from([9; 16]));
+  check_gen_round_trip(Xorshiftplus128v1Generator::from([1, 2]));
+  check_gen_round_trip(Xorshiftplus128v2Generator::from([1, 2]));
+  check_gen_round_trip(Xoroshiro1024starstarGenerator::from([9; 16]));
+  check_gen_round_trip(Xoroshiro128plusplusGenerator::from([1, 2]));
+  check_gen_round_trip(Xoshiro512starstarGenerator::from([1, 2, 3, 4, 5, 6, 7, 8]));
+}
+
+#[test]
+fn test_snapshot_rejects_corruption() {
+  let gen = ChaCha20Generator::from_parts(b"expand 32-byte k", [7; 32], 1, 2);
+  let mut snapshot = Vec::new();
+  gen.save(&mut snapshot).unwrap();
+  assert!(ChaCha20Generator::restore(&snapshot[..]).is_ok());
+  for i in 0 .. snapshot.len() {
+    let mut corrupt = snapshot.clone();
+    corrupt[i] ^= 0x10;
+    let e = ChaCha20Generator::restore(&corrupt[..]).unwrap_err();
+    assert!(e.kind() == IoErrorKind::InvalidData || e.kind() == IoErrorKind::UnexpectedEof);
+  }
+  let e = ChaCha20Generator::restore(&snapshot[.. snapshot.len() - 1]).unwrap_err();
+  assert_eq!(e.kind(), IoErrorKind::UnexpectedEof);
+  let e = ChaCha12Generator::restore(&snapshot[..]).unwrap_err();
+  assert_eq!(e.kind(), IoErrorKind::InvalidData);
+  let e = ChaCha20Stream::restore(&snapshot[..]).unwrap_err();
+  assert_eq!(e.kind(), IoErrorKind::InvalidData);
+  let mut stream = ChaCha20Stream::new(gen);
+  stream.next_u8();
+  let mut snapshot = Vec::new();
+  stream.save(&mut snapshot).unwrap();
+  let e = ChaCha20WideStream::restore(&snapshot[..]).unwrap_err();
+  assert_eq!(e.kind(), IoErrorKind::InvalidData);
+}
+
+#[test]
+fn test_snapshot_rejects_invalid_state() {
+  let mut payload = Vec::new();
+  for _ in 0 .. 16 {
+    payload.extend_from_slice(&1_u64.to_le_bytes());
+  }
+  payload.push(16);
+  let snapshot = encode_snapshot("Xoroshiro1024Generator", &payload);
+  assert!(Xoroshiro1024Generator::restore(&snapshot[..]).is_err());
+  let snapshot = encode_snapshot("Xorshiftplus128v1Generator", &[0; 16]);
+  assert!(Xorshiftplus128v1Generator::restore(&snapshot[..]).is_err());
+  let snapshot = encode_snapshot("Romu32x4Generator", &[0; 16]);
+  assert!(Romu32x4Generator::restore(&snapshot[..]).is_err());
+  let snapshot = encode_snapshot("Splitmix64Generator", &[0; 9]);
+  assert!(Splitmix64Generator::restore(&snapshot[..]).is_err());
+}
+}
@@ -1,4 +1,11 @@
 use crate::{Generator, Buffer64};
+use crate::snapshot::{Snapshot};
+
+use byteorder::{ReadBytesExt, LittleEndian as LE};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize};
+
+use std::io::{Error as IoError};
 
 /* splitmix64_next:
 
@@ -20,6 +27,8 @@ pub fn splitmix64_next(state: &mut u64) -> u64 {
   z ^ (z >> 31)
 }
 
+#[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Splitmix64Generator {
   state: u64,
 }
@@ -36,4 +45,18 @@ impl Generator<[u64; 1]> for Splitmix64Generator {
   }
 }
 
+impl Snapshot for Splitmix64Generator {
+  const TAG: &'static str = "Splitmix64Generator";
+
+  fn save_state(&self, out: &mut Vec<u8>) {
+    out.extend_from_slice(&self.state.to_le_bytes());
+  }
+
+  fn restore_state(state: &mut &[u8]) -> Result<Splitmix64Generator, IoError> {
+    Ok(Splitmix64Generator{state: state.read_u64::<LE>()?})
+  }
+}
+
+impl_stream_gen64!(Splitmix64Generator);
+
 pub type Splitmix64Stream = Buffer64<Splitmix64Generator, [u64; 1]>;
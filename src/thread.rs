@@ -0,0 +1,104 @@
+use crate::{Stream};
+use crate::dist::{DrawNew};
+use crate::drbg::{ChaChaDrbg};
+
+use std::cell::{RefCell};
+use std::io::{Read, Error as IoError};
+
+pub const THREAD_RESEED_BYTES: u64 = 1 << 20;
+
+thread_local! {
+  static THREAD_DRBG: RefCell<Option<ChaChaDrbg>> = const { RefCell::new(None) };
+}
+
+fn with_thread_drbg<T, F: FnOnce(&mut ChaChaDrbg) -> Result<T, IoError>>(f: F) -> Result<T, IoError> {
+  THREAD_DRBG.with(|cell| {
+    let mut drbg = cell.borrow_mut();
+    if drbg.is_none() {
+      let mut d = ChaChaDrbg::from_os()?;
+      d.set_reseed_interval(Some(THREAD_RESEED_BYTES));
+      *drbg = Some(d);
+    }
+    f(drbg.as_mut().unwrap())
+  })
+}
+
+/// Handle to the calling thread's `ChaChaDrbg`, which is seeded from
+/// `os::RandomStream` on first use and reseeded every `THREAD_RESEED_BYTES`
+/// bytes and after a `fork`.
+///
+/// The handle carries no state, so it may be copied freely and always draws
+/// from the generator of the thread that uses it. `Read` reports a failure
+/// to seed from the OS as an error, while `Stream` panics on it.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ThreadRng {
+}
+
+pub fn thread_rng() -> ThreadRng {
+  ThreadRng{}
+}
+
+/// Draws a value of type `T` from the thread-local generator.
+pub fn random<T: DrawNew>() -> T {
+  T::draw_new(thread_rng())
+}
+
+impl Read for ThreadRng {
+  fn read(&mut self, buf: &mut [u8]) -> Result<usize, IoError> {
+    with_thread_drbg(|drbg| drbg.read(buf))
+  }
+}
+
+impl Stream for ThreadRng {
+  #[inline]
+  fn next_u8(&mut self) -> u8 {
+    let mut buf = [0; 1];
+    self.fill_bytes(&mut buf);
+    buf[0]
+  }
+
+  fn fill_bytes(&mut self, buf: &mut [u8]) {
+    self.read_exact(buf).unwrap();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+use super::{random, thread_rng};
+use crate::dist::{Draw, shuffle};
+
+use std::io::{Read};
+use std::thread;
+
+#[test]
+fn test_thread_rng_draws() {
+  let mut rng = thread_rng();
+  let mut buf = [0; 64];
+  rng.read_exact(&mut buf).unwrap();
+  assert!(buf.iter().any(|&x| x != 0));
+  for _ in 0 .. 100 {
+    assert!((10 .. 20_u32).draw(&mut rng) >= 10);
+    let x = random::<f64>();
+    assert!((0.0 .. 1.0).contains(&x));
+    let y = random::<f32>();
+    assert!((0.0 .. 1.0).contains(&y));
+  }
+  assert_ne!(random::<u64>(), random::<u64>());
+  let mut perm: Vec<u32> = (0 .. 100).collect();
+  shuffle(&mut perm, thread_rng());
+  perm.sort();
+  assert_eq!(perm, (0 .. 100).collect::<Vec<u32>>());
+}
+
+#[test]
+fn test_thread_rng_per_thread() {
+  let xs: Vec<[u64; 4]> = (0 .. 4).map(|_| thread::spawn(|| {
+    [random(), random(), random(), random()]
+  })).collect::<Vec<_>>().into_iter().map(|h| h.join().unwrap()).collect();
+  for i in 0 .. xs.len() {
+    for j in 0 .. i {
+      assert_ne!(xs[i], xs[j]);
+    }
+  }
+}
+}
@@ -1,10 +1,16 @@
-use crate::{Generator, Buffer64};
+use crate::{Generator, Buffer64, seek_word, word_stream_position};
+use crate::gf2::{x_pow, x_pow_inv};
+use crate::snapshot::{Snapshot, invalid_snapshot, restore_u64s, save_u64s};
 
 use byteorder::{ReadBytesExt, LittleEndian as LE};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Deserialize, Deserializer};
+#[cfg(feature = "serde")]
+use serde::de::{Error as DeError};
 
-use std::io::{Read};
+use std::io::{Read, Seek, SeekFrom, Error as IoError};
 
-/* xoroshiro1024_next, xorshiftplus128v1_next, xorshiftplus128v2_next:
+/* xoroshiro*_next, xoshiro*_next, xorshiftplus128v1_next, xorshiftplus128v2_next:
 
 Written in _ by Sebastiano Vigna (vigna@acm.org)
 
@@ -14,49 +20,447 @@ worldwide. This software is distributed without any warranty.
 
 See <http://creativecommons.org/publicdomain/zero/1.0/>. */
 
-// This is the xoroshiro1024++ variant.
-pub fn xoroshiro1024_next(state: &mut [u64; 16], cursor: &mut u8) -> u64 {
+/// Steps the linear engine shared by the xoroshiro1024 variants and returns
+/// the words `s0` and `s15` that the scramblers are applied to.
+#[inline]
+fn xoroshiro1024_step(state: &mut [u64; 16], cursor: &mut u8) -> (u64, u64) {
   let q = *cursor;
   let np = q.wrapping_add(1) & 15;
   let s15 = state[q as usize];
   let s0 = state[np as usize];
-  let out = ((s0.wrapping_add(s15)).rotate_left(23)).wrapping_add(s15);
-  let s15 = s0 ^ s15;
-  state[q as usize] = (s0.rotate_left(25)) ^ s15 ^ (s15.rotate_left(27));
-  state[np as usize] = s15.rotate_left(36);
+  let t = s0 ^ s15;
+  state[q as usize] = (s0.rotate_left(25)) ^ t ^ (t << 27);
+  state[np as usize] = t.rotate_left(36);
   *cursor = np;
-  out
+  (s0, s15)
 }
 
-pub struct Xoroshiro1024Generator {
-  state: [u64; 16],
-  cursor: u8,
+// This is the xoroshiro1024++ variant.
+pub fn xoroshiro1024_next(state: &mut [u64; 16], cursor: &mut u8) -> u64 {
+  let (s0, s15) = xoroshiro1024_step(state, cursor);
+  ((s0.wrapping_add(s15)).rotate_left(23)).wrapping_add(s15)
 }
 
-impl From<[u64; 16]> for Xoroshiro1024Generator {
-  fn from(state: [u64; 16]) -> Xoroshiro1024Generator {
-    Xoroshiro1024Generator{state, cursor: 0}
-  }
+pub fn xoroshiro1024star_next(state: &mut [u64; 16], cursor: &mut u8) -> u64 {
+  let (s0, _) = xoroshiro1024_step(state, cursor);
+  s0.wrapping_mul(0x9e3779b97f4a7c13)
 }
 
-impl<'r> From<&'r mut dyn Read> for Xoroshiro1024Generator {
-  fn from(reader: &'r mut dyn Read) -> Xoroshiro1024Generator {
-    let mut state = [0; 16];
-    for k in 0 .. 16 {
-      state[k] = reader.read_u64::<LE>().unwrap();
+pub fn xoroshiro1024starstar_next(state: &mut [u64; 16], cursor: &mut u8) -> u64 {
+  let (s0, _) = xoroshiro1024_step(state, cursor);
+  s0.wrapping_mul(5).rotate_left(7).wrapping_mul(9)
+}
+
+// Characteristic polynomial of xoroshiro1024, without the x^1024 term.
+const XOROSHIRO1024_CHARPOLY: [u64; 16] = [
+  0x5cfeb8cc48ddb211, 0xb73e379d035a06dd, 0x17d5100a20a0350e, 0x7550223f68f98cac,
+  0x29d373b5c5ed3459, 0x3689b412ef70de48, 0xa1d3b6ee079a7cc6, 0x9bf0b669abd100f8,
+  0x955c84e105f60997, 0x6ca140c61889cddd, 0xabaf68c5fc3a0e4a, 0xa46134526b83adc5,
+  0x0710704d05683d63, 0x580d080b44b606a2, 0x008040a0580158a1, 0x0000000000800081,
+];
+
+// Jump polynomials for x^(2^512) and x^(2^768) modulo the characteristic
+// polynomial of xoroshiro1024, as published with the reference C code.
+const XOROSHIRO1024_JUMP: [u64; 16] = [
+  0x931197d8e3177f17, 0xb59422e0b9138c5f, 0xf06a6afb49d668bb, 0xacb8a6412c8a1401,
+  0x12304ec85f0b3468, 0xb7dfe7079209891e, 0x405b7eec77d9eb14, 0x34ead68280c44e4a,
+  0xe0e4ba3e0ac9e366, 0x8f46eda8348905b7, 0x328bf4dbad90d6ff, 0xc8fd6fb31c9effc3,
+  0xe899d452d4b67652, 0x45f387286ade3205, 0x03864f454a8920bd, 0xa68fa28725b1b384,
+];
+const XOROSHIRO1024_LONG_JUMP: [u64; 16] = [
+  0x7374156360bbf00f, 0x4630c2efa3b3c1f6, 0x6654183a892786b1, 0x94f7bfcbfb0f1661,
+  0x27d8243d3d13eb2d, 0x9701730f3dfb300f, 0x2f293baae6f604ad, 0xa661831cb60cd8b6,
+  0x68280c77d9fe008c, 0x50554160f5ba9459, 0x2fc20b17ec7b2a9a, 0x49189bbdc8ec9f8f,
+  0x92a65bca41852cc1, 0xf46820dd0509c12a, 0x52b00c35fbf92185, 0x1e5b3b7f589e03c1,
+];
+
+/// Replaces the state with the sum of the states reached after each power
+/// of x that occurs in the jump polynomial `poly`. Along the way the cursor
+/// goes around 1024 times and ends up where it started.
+fn xoroshiro1024_jump_poly(state: &mut [u64; 16], cursor: &mut u8, poly: &[u64; 16]) {
+  let mut t = [0; 16];
+  for &w in poly.iter() {
+    for b in 0 .. 64 {
+      if w & (1 << b) != 0 {
+        for j in 0 .. 16 {
+          t[j] ^= state[(j + *cursor as usize) & 15];
+        }
+      }
+      xoroshiro1024_step(state, cursor);
     }
-    Xoroshiro1024Generator{state, cursor: 0}
+  }
+  for j in 0 .. 16 {
+    state[(j + *cursor as usize) & 15] = t[j];
   }
 }
 
-impl Generator<[u64; 1]> for Xoroshiro1024Generator {
-  #[inline]
-  fn next_gen(&mut self, out: &mut [u64; 1]) {
-    out[0] = xoroshiro1024_next(&mut self.state, &mut self.cursor);
+macro_rules! xoroshiro1024_generator {
+  ($gen:ident, $stream:ident, $next:ident) => {
+    /// Seeks by output word with `seek_word`; `jump` and `long_jump` leave
+    /// the position as it is.
+    #[derive(PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize))]
+    pub struct $gen {
+      state: [u64; 16],
+      cursor: u8,
+      pos: u64,
+    }
+
+    impl $gen {
+      /// Advances by 2^512 steps, e.g. to give each of up to 2^512 parallel
+      /// workers a non-overlapping substream.
+      pub fn jump(&mut self) {
+        xoroshiro1024_jump_poly(&mut self.state, &mut self.cursor, &XOROSHIRO1024_JUMP);
+      }
+
+      /// Advances by 2^768 steps.
+      pub fn long_jump(&mut self) {
+        xoroshiro1024_jump_poly(&mut self.state, &mut self.cursor, &XOROSHIRO1024_LONG_JUMP);
+      }
+
+      /// Advances by `delta` steps.
+      pub fn advance(&mut self, delta: u128) {
+        let poly = x_pow(delta, &XOROSHIRO1024_CHARPOLY);
+        xoroshiro1024_jump_poly(&mut self.state, &mut self.cursor, &poly);
+        self.set_cursor(self.cursor.wrapping_add(delta as u8) & 15);
+        self.pos = self.pos.wrapping_add(delta as u64);
+      }
+
+      /// Goes back by `delta` steps.
+      pub fn rewind(&mut self, delta: u128) {
+        let poly = x_pow_inv(delta, &XOROSHIRO1024_CHARPOLY);
+        xoroshiro1024_jump_poly(&mut self.state, &mut self.cursor, &poly);
+        self.set_cursor(self.cursor.wrapping_sub(delta as u8) & 15);
+        self.pos = self.pos.wrapping_sub(delta as u64);
+      }
+
+      /// Rotates the state so that the cursor ends up at `cursor`, which leaves
+      /// the output unchanged and gives the same representation as stepping.
+      fn set_cursor(&mut self, cursor: u8) {
+        let old = self.state;
+        for j in 0 .. 16 {
+          self.state[(j + cursor as usize) & 15] = old[(j + self.cursor as usize) & 15];
+        }
+        self.cursor = cursor;
+      }
+
+      /// Rejects states the generator could never reach.
+      fn try_from_parts(state: [u64; 16], cursor: u8, pos: u64) -> Result<$gen, IoError> {
+        if cursor >= 16 {
+          return Err(invalid_snapshot(format!(concat!(stringify!($gen), " cursor {} is out of range"), cursor)));
+        }
+        if state == [0; 16] {
+          return Err(invalid_snapshot(concat!(stringify!($gen), " has an all-zero state")));
+        }
+        Ok($gen{state, cursor, pos})
+      }
+    }
+
+    #[cfg(feature = "serde")]
+    impl<'de> Deserialize<'de> for $gen {
+      fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Unchecked twin of the generator; the shared name keeps the
+        // serialized form the same.
+        #[derive(Deserialize)]
+        struct $gen {
+          state: [u64; 16],
+          cursor: u8,
+          pos: u64,
+        }
+        let raw = $gen::deserialize(deserializer)?;
+        Self::try_from_parts(raw.state, raw.cursor, raw.pos).map_err(D::Error::custom)
+      }
+    }
+
+    impl From<[u64; 16]> for $gen {
+      fn from(state: [u64; 16]) -> $gen {
+        $gen{state, cursor: 0, pos: 0}
+      }
+    }
+
+    impl<'r> From<&'r mut dyn Read> for $gen {
+      fn from(reader: &'r mut dyn Read) -> $gen {
+        let mut state = [0; 16];
+        for k in 0 .. 16 {
+          state[k] = reader.read_u64::<LE>().unwrap();
+        }
+        $gen{state, cursor: 0, pos: 0}
+      }
+    }
+
+    impl Generator<[u64; 1]> for $gen {
+      #[inline]
+      fn next_gen(&mut self, out: &mut [u64; 1]) {
+        out[0] = $next(&mut self.state, &mut self.cursor);
+        self.pos = self.pos.wrapping_add(1);
+      }
+    }
+
+    impl Seek for $gen {
+      fn seek(&mut self, pos: SeekFrom) -> Result<u64, IoError> {
+        let target = seek_word(self.pos, 8, pos)?;
+        if target >= self.pos {
+          self.advance((target - self.pos) as u128);
+        } else {
+          self.rewind((self.pos - target) as u128);
+        }
+        Ok(target * 8)
+      }
+
+      fn stream_position(&mut self) -> Result<u64, IoError> {
+        word_stream_position(self.pos, 8)
+      }
+    }
+
+    impl Snapshot for $gen {
+      const TAG: &'static str = stringify!($gen);
+
+      fn save_state(&self, out: &mut Vec<u8>) {
+        save_u64s(out, &self.state);
+        out.push(self.cursor);
+        save_u64s(out, &[self.pos]);
+      }
+
+      fn restore_state(state: &mut &[u8]) -> Result<$gen, IoError> {
+        let mut s = [0; 16];
+        restore_u64s(state, &mut s)?;
+        let cursor = state.read_u8()?;
+        let mut pos = [0];
+        restore_u64s(state, &mut pos)?;
+        $gen::try_from_parts(s, cursor, pos[0])
+      }
+    }
+
+    impl_stream_gen64!($gen);
+
+    pub type $stream = Buffer64<$gen, [u64; 1]>;
+  };
+}
+
+xoroshiro1024_generator!(Xoroshiro1024Generator, Xoroshiro1024Stream, xoroshiro1024_next);
+xoroshiro1024_generator!(Xoroshiro1024starGenerator, Xoroshiro1024starStream, xoroshiro1024star_next);
+xoroshiro1024_generator!(Xoroshiro1024starstarGenerator, Xoroshiro1024starstarStream, xoroshiro1024starstar_next);
+
+/// Replaces the state with the sum of the states reached after each power
+/// of x that occurs in the jump polynomial `poly`.
+fn jump_poly<const N: usize>(state: &mut [u64; N], poly: &[u64; N], next: fn(&mut [u64; N]) -> u64) {
+  let mut t = [0; N];
+  for &w in poly.iter() {
+    for b in 0 .. 64 {
+      if w & (1 << b) != 0 {
+        for j in 0 .. N {
+          t[j] ^= state[j];
+        }
+      }
+      next(state);
+    }
   }
+  *state = t;
+}
+
+/// Snapshot and deserialization for generators whose state is a single
+/// array that must not be all zero.
+macro_rules! fixed_state_checks {
+  ($gen:ident, $n:expr) => {
+    impl $gen {
+      fn try_from_state(state: [u64; $n]) -> Result<$gen, IoError> {
+        if state == [0; $n] {
+          return Err(invalid_snapshot(concat!(stringify!($gen), " has an all-zero state")));
+        }
+        Ok($gen{state})
+      }
+    }
+
+    #[cfg(feature = "serde")]
+    impl<'de> Deserialize<'de> for $gen {
+      fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Unchecked twin of the generator; the shared name keeps the
+        // serialized form the same.
+        #[derive(Deserialize)]
+        struct $gen {
+          state: [u64; $n],
+        }
+        let raw = $gen::deserialize(deserializer)?;
+        Self::try_from_state(raw.state).map_err(D::Error::custom)
+      }
+    }
+
+    impl Snapshot for $gen {
+      const TAG: &'static str = stringify!($gen);
+
+      fn save_state(&self, out: &mut Vec<u8>) {
+        save_u64s(out, &self.state);
+      }
+
+      fn restore_state(state: &mut &[u8]) -> Result<$gen, IoError> {
+        let mut s = [0; $n];
+        restore_u64s(state, &mut s)?;
+        $gen::try_from_state(s)
+      }
+    }
+  };
+}
+
+#[inline]
+fn xoshiro256_step(s: &mut [u64; 4]) {
+  let t = s[1] << 17;
+  s[2] ^= s[0];
+  s[3] ^= s[1];
+  s[1] ^= s[2];
+  s[0] ^= s[3];
+  s[2] ^= t;
+  s[3] = s[3].rotate_left(45);
+}
+
+pub fn xoshiro256plusplus_next(state: &mut [u64; 4]) -> u64 {
+  let r = state[0].wrapping_add(state[3]).rotate_left(23).wrapping_add(state[0]);
+  xoshiro256_step(state);
+  r
+}
+
+pub fn xoshiro256starstar_next(state: &mut [u64; 4]) -> u64 {
+  let r = state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+  xoshiro256_step(state);
+  r
+}
+
+pub fn xoshiro256plus_next(state: &mut [u64; 4]) -> u64 {
+  let r = state[0].wrapping_add(state[3]);
+  xoshiro256_step(state);
+  r
+}
+
+#[inline]
+fn xoroshiro128_step(s: &mut [u64; 2], a: u32, b: u32, c: u32) {
+  let s0 = s[0];
+  let s1 = s[1] ^ s0;
+  s[0] = s0.rotate_left(a) ^ s1 ^ (s1 << b);
+  s[1] = s1.rotate_left(c);
 }
 
-pub type Xoroshiro1024Stream = Buffer64<Xoroshiro1024Generator, [u64; 1]>;
+// The ++ variant uses different rotation and shift constants.
+pub fn xoroshiro128plusplus_next(state: &mut [u64; 2]) -> u64 {
+  let r = state[0].wrapping_add(state[1]).rotate_left(17).wrapping_add(state[0]);
+  xoroshiro128_step(state, 49, 21, 28);
+  r
+}
+
+pub fn xoroshiro128starstar_next(state: &mut [u64; 2]) -> u64 {
+  let r = state[0].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+  xoroshiro128_step(state, 24, 16, 37);
+  r
+}
+
+pub fn xoroshiro128plus_next(state: &mut [u64; 2]) -> u64 {
+  let r = state[0].wrapping_add(state[1]);
+  xoroshiro128_step(state, 24, 16, 37);
+  r
+}
+
+#[inline]
+fn xoshiro512_step(s: &mut [u64; 8]) {
+  let t = s[1] << 11;
+  s[2] ^= s[0];
+  s[5] ^= s[1];
+  s[1] ^= s[2];
+  s[7] ^= s[3];
+  s[3] ^= s[4];
+  s[4] ^= s[5];
+  s[0] ^= s[6];
+  s[6] ^= s[7];
+  s[6] ^= t;
+  s[7] = s[7].rotate_left(21);
+}
+
+pub fn xoshiro512plusplus_next(state: &mut [u64; 8]) -> u64 {
+  let r = state[0].wrapping_add(state[2]).rotate_left(17).wrapping_add(state[2]);
+  xoshiro512_step(state);
+  r
+}
+
+pub fn xoshiro512starstar_next(state: &mut [u64; 8]) -> u64 {
+  let r = state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+  xoshiro512_step(state);
+  r
+}
+
+// Jump polynomials as published with the reference C code. The ++ variant
+// of xoroshiro128 has its own engine and hence its own jumps.
+const XOSHIRO256_JUMP: [u64; 4] = [0x180ec6d33cfd0aba, 0xd5a61266f0c9392c, 0xa9582618e03fc9aa, 0x39abdc4529b1661c];
+const XOSHIRO256_LONG_JUMP: [u64; 4] = [0x76e15d3efefdcbbf, 0xc5004e441c522fb3, 0x77710069854ee241, 0x39109bb02acbe635];
+const XOROSHIRO128_JUMP: [u64; 2] = [0xdf900294d8f554a5, 0x170865df4b3201fc];
+const XOROSHIRO128_LONG_JUMP: [u64; 2] = [0xd2a98b26625eee7b, 0xdddf9b1090aa7ac1];
+const XOROSHIRO128PLUSPLUS_JUMP: [u64; 2] = [0x2bd7a6a6e99c2ddc, 0x0992ccaf6a6fca05];
+const XOROSHIRO128PLUSPLUS_LONG_JUMP: [u64; 2] = [0x360fd5f2cf8d5d99, 0x9c6e6877736c46e3];
+const XOSHIRO512_JUMP: [u64; 8] = [
+  0x33ed89b6e7a353f9, 0x760083d7955323be, 0x2837f2fbb5f22fae, 0x4b8c5674d309511c,
+  0xb11ac47a7ba28c25, 0xf1be7667092bcc1c, 0x53851efdb6df0aaf, 0x1ebbc8b23eaf25db,
+];
+const XOSHIRO512_LONG_JUMP: [u64; 8] = [
+  0x11467fef8f921d28, 0xa2a819f2e79c8ea8, 0xa8299fc284b3959a, 0xb4d347340ca63ee1,
+  0x1cb0940bedbff6ce, 0xd956c5c4fa1f8e17, 0x915e38fd4eda93bc, 0x5b3ccdfa5d7daca5,
+];
+
+macro_rules! xoshiro_generator {
+  ($gen:ident, $stream:ident, $n:expr, $next:ident, $jump:ident, $long_jump:ident, $jump_log2:literal, $long_jump_log2:literal) => {
+    #[derive(PartialEq, Eq, Debug)]
+    #[cfg_attr(feature = "serde", derive(Serialize))]
+    pub struct $gen {
+      state: [u64; $n],
+    }
+
+    impl $gen {
+      #[doc = concat!("Advances by 2^", $jump_log2, " steps, e.g. to give each of up to 2^", $jump_log2, " parallel")]
+      /// workers a non-overlapping substream.
+      pub fn jump(&mut self) {
+        jump_poly(&mut self.state, &$jump, $next);
+      }
+
+      #[doc = concat!("Advances by 2^", $long_jump_log2, " steps.")]
+      pub fn long_jump(&mut self) {
+        jump_poly(&mut self.state, &$long_jump, $next);
+      }
+    }
+
+    impl From<[u64; $n]> for $gen {
+      fn from(state: [u64; $n]) -> $gen {
+        $gen{state}
+      }
+    }
+
+    impl<'r> From<&'r mut dyn Read> for $gen {
+      fn from(reader: &'r mut dyn Read) -> $gen {
+        let mut state = [0; $n];
+        for k in 0 .. $n {
+          state[k] = reader.read_u64::<LE>().unwrap();
+        }
+        $gen{state}
+      }
+    }
+
+    impl Generator<[u64; 1]> for $gen {
+      #[inline]
+      fn next_gen(&mut self, out: &mut [u64; 1]) {
+        out[0] = $next(&mut self.state);
+      }
+    }
+
+    fixed_state_checks!($gen, $n);
+
+    impl_stream_gen64!($gen);
+
+    pub type $stream = Buffer64<$gen, [u64; 1]>;
+  };
+}
+
+xoshiro_generator!(Xoshiro256plusplusGenerator, Xoshiro256plusplusStream, 4, xoshiro256plusplus_next, XOSHIRO256_JUMP, XOSHIRO256_LONG_JUMP, 128, 192);
+xoshiro_generator!(Xoshiro256starstarGenerator, Xoshiro256starstarStream, 4, xoshiro256starstar_next, XOSHIRO256_JUMP, XOSHIRO256_LONG_JUMP, 128, 192);
+xoshiro_generator!(Xoshiro256plusGenerator, Xoshiro256plusStream, 4, xoshiro256plus_next, XOSHIRO256_JUMP, XOSHIRO256_LONG_JUMP, 128, 192);
+xoshiro_generator!(Xoroshiro128plusplusGenerator, Xoroshiro128plusplusStream, 2, xoroshiro128plusplus_next, XOROSHIRO128PLUSPLUS_JUMP, XOROSHIRO128PLUSPLUS_LONG_JUMP, 64, 96);
+xoshiro_generator!(Xoroshiro128starstarGenerator, Xoroshiro128starstarStream, 2, xoroshiro128starstar_next, XOROSHIRO128_JUMP, XOROSHIRO128_LONG_JUMP, 64, 96);
+xoshiro_generator!(Xoroshiro128plusGenerator, Xoroshiro128plusStream, 2, xoroshiro128plus_next, XOROSHIRO128_JUMP, XOROSHIRO128_LONG_JUMP, 64, 96);
+xoshiro_generator!(Xoshiro512plusplusGenerator, Xoshiro512plusplusStream, 8, xoshiro512plusplus_next, XOSHIRO512_JUMP, XOSHIRO512_LONG_JUMP, 256, 384);
+xoshiro_generator!(Xoshiro512starstarGenerator, Xoshiro512starstarStream, 8, xoshiro512starstar_next, XOSHIRO512_JUMP, XOSHIRO512_LONG_JUMP, 256, 384);
 
 pub fn xorshiftplus128v1_next(state: &mut [u64; 2]) -> u64 {
   let mut s1 = state[0];
@@ -78,6 +482,20 @@ pub fn xorshiftplus128v2_next(state: &mut [u64; 2]) -> u64 {
   r
 }
 
+// Characteristic polynomials of xorshift128+, without the x^128 term.
+const XORSHIFTPLUS128V1_CHARPOLY: [u64; 2] = [0xbd82fd40e01730f9, 0x01f9f801f6fd0098];
+const XORSHIFTPLUS128V2_CHARPOLY: [u64; 2] = [0x024f06fae9e61daf, 0x2844c5d42caf7db0];
+
+// Jump polynomials for x^(2^64) and x^(2^96). The v2 jump is the one
+// published with the reference C code; the others are computed from the
+// characteristic polynomials in the same way.
+const XORSHIFTPLUS128V1_JUMP: [u64; 2] = [0x8c405782bca686ad, 0xc44f35946fef49c6];
+const XORSHIFTPLUS128V1_LONG_JUMP: [u64; 2] = [0xeec5431970b882bc, 0x397adbe826b37b9e];
+const XORSHIFTPLUS128V2_JUMP: [u64; 2] = [0x8a5cd789635d2dff, 0x121fd2155c472f96];
+const XORSHIFTPLUS128V2_LONG_JUMP: [u64; 2] = [0xea61c9f1f13962ae, 0xa1fe50ef79cfafb2];
+
+#[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Xorshiftplus128v1Generator {
   state: [u64; 2],
 }
@@ -95,6 +513,8 @@ impl Generator<[u64; 1]> for Xorshiftplus128v1Generator {
   }
 }
 
+#[derive(PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct Xorshiftplus128v2Generator {
   state: [u64; 2],
 }
@@ -111,3 +531,326 @@ impl Generator<[u64; 1]> for Xorshiftplus128v2Generator {
     out[0] = xorshiftplus128v2_next(&mut self.state);
   }
 }
+
+macro_rules! xorshiftplus128_jump {
+  ($gen:ident, $next:ident, $charpoly:ident, $jump:ident, $long_jump:ident) => {
+    impl $gen {
+      /// Advances by 2^64 steps, e.g. to give each of up to 2^64 parallel
+      /// workers a non-overlapping substream.
+      pub fn jump(&mut self) {
+        jump_poly(&mut self.state, &$jump, $next);
+      }
+
+      /// Advances by 2^96 steps.
+      pub fn long_jump(&mut self) {
+        jump_poly(&mut self.state, &$long_jump, $next);
+      }
+
+      /// Advances by `delta` steps.
+      pub fn advance(&mut self, delta: u128) {
+        jump_poly(&mut self.state, &x_pow(delta, &$charpoly), $next);
+      }
+
+      /// Goes back by `delta` steps.
+      pub fn rewind(&mut self, delta: u128) {
+        jump_poly(&mut self.state, &x_pow_inv(delta, &$charpoly), $next);
+      }
+    }
+  };
+}
+
+xorshiftplus128_jump!(Xorshiftplus128v1Generator, xorshiftplus128v1_next, XORSHIFTPLUS128V1_CHARPOLY, XORSHIFTPLUS128V1_JUMP, XORSHIFTPLUS128V1_LONG_JUMP);
+xorshiftplus128_jump!(Xorshiftplus128v2Generator, xorshiftplus128v2_next, XORSHIFTPLUS128V2_CHARPOLY, XORSHIFTPLUS128V2_JUMP, XORSHIFTPLUS128V2_LONG_JUMP);
+
+fixed_state_checks!(Xorshiftplus128v1Generator, 2);
+fixed_state_checks!(Xorshiftplus128v2Generator, 2);
+
+impl_stream_gen64!(Xorshiftplus128v1Generator);
+impl_stream_gen64!(Xorshiftplus128v2Generator);
+
+#[cfg(test)]
+mod tests {
+use super::*;
+use crate::{Stream};
+use crate::gf2::{powmod, x_pow};
+
+use std::io::{Read, Seek, SeekFrom, ErrorKind as IoErrorKind};
+
+// Expected outputs are from the reference C implementations, except for the
+// xorshift128+ jumps other than the v2 jump, which have no published
+// reference; see `test_xorshiftplus128_jump`.
+
+fn xoroshiro1024_test_gen() -> Xoroshiro1024Generator {
+  let mut state = [0; 16];
+  for (k, x) in state.iter_mut().enumerate() {
+    *x = k as u64 + 1;
+  }
+  let mut gen = Xoroshiro1024Generator::from(state);
+  for _ in 0 .. 5 {
+    gen.next_u64();
+  }
+  gen
+}
+
+#[test]
+fn test_xoroshiro1024_reference() {
+  let mut gen = xoroshiro1024_test_gen();
+  for _ in 0 .. 95 {
+    gen.next_u64();
+  }
+  assert_eq!(gen.next_u64(), 0x91c81ddb4108902e);
+  assert_eq!(gen.next_u64(), 0x51a1fe789486d6c4);
+  assert_eq!(gen.next_u64(), 0x15a9ef13844ef17e);
+}
+
+#[test]
+fn test_xoroshiro1024_jump() {
+  let mut gen = xoroshiro1024_test_gen();
+  gen.jump();
+  assert_eq!(gen.cursor, 5);
+  assert_eq!(gen.next_u64(), 0x019f06bf0aabde7b);
+  assert_eq!(gen.next_u64(), 0x8f5b9da43ac58628);
+  assert_eq!(gen.next_u64(), 0xfe47db7bd65fc395);
+  let mut gen = xoroshiro1024_test_gen();
+  gen.long_jump();
+  assert_eq!(gen.next_u64(), 0x3c9526a150c82674);
+  assert_eq!(gen.next_u64(), 0x452c0e1016b18aa7);
+  assert_eq!(gen.next_u64(), 0xadce3cde0362d509);
+}
+
+#[test]
+fn test_xorshiftplus128_jump() {
+  // The v1 jumps and the v2 long jump are derived from the characteristic
+  // polynomials by this code, so check the polynomials against x^(2^64)
+  // and x^(2^96), and `jump_poly` against stepping, rather than trusting
+  // the outputs below on their own.
+  assert_eq!(x_pow(1 << 64, &XORSHIFTPLUS128V1_CHARPOLY), XORSHIFTPLUS128V1_JUMP);
+  assert_eq!(x_pow(1 << 96, &XORSHIFTPLUS128V1_CHARPOLY), XORSHIFTPLUS128V1_LONG_JUMP);
+  assert_eq!(x_pow(1 << 64, &XORSHIFTPLUS128V2_CHARPOLY), XORSHIFTPLUS128V2_JUMP);
+  assert_eq!(x_pow(1 << 96, &XORSHIFTPLUS128V2_CHARPOLY), XORSHIFTPLUS128V2_LONG_JUMP);
+  for &d in [0, 1, 5, 63, 64, 100].iter() {
+    let mut gen = Xorshiftplus128v1Generator::from([1, 2]);
+    jump_poly(&mut gen.state, &x_pow(d, &XORSHIFTPLUS128V1_CHARPOLY), xorshiftplus128v1_next);
+    let mut expected = Xorshiftplus128v1Generator::from([1, 2]);
+    for _ in 0 .. d {
+      expected.next_u64();
+    }
+    assert_eq!(gen, expected);
+  }
+  let mut gen = Xorshiftplus128v1Generator::from([1, 2]);
+  gen.jump();
+  assert_eq!(gen.next_u64(), 0xe0779a2aa6946409);
+  assert_eq!(gen.next_u64(), 0x4a4d4951e8d1cd88);
+  assert_eq!(gen.next_u64(), 0x6c6a09e17d63772f);
+  let mut gen = Xorshiftplus128v1Generator::from([1, 2]);
+  gen.long_jump();
+  assert_eq!(gen.next_u64(), 0x44dd64b5f8f4a909);
+  assert_eq!(gen.next_u64(), 0xb018371e219931cc);
+  assert_eq!(gen.next_u64(), 0xd03e7b7b1448004d);
+  let mut gen = Xorshiftplus128v2Generator::from([1, 2]);
+  gen.jump();
+  assert_eq!(gen.next_u64(), 0x374f86814f9700a9);
+  assert_eq!(gen.next_u64(), 0x17271b1b3446d495);
+  assert_eq!(gen.next_u64(), 0xa5f03a4f774e88df);
+  let mut gen = Xorshiftplus128v2Generator::from([1, 2]);
+  gen.long_jump();
+  assert_eq!(gen.next_u64(), 0xa7b74c060de8f423);
+  assert_eq!(gen.next_u64(), 0x9b151bd1087330e6);
+  assert_eq!(gen.next_u64(), 0xf84172649b378436);
+}
+
+#[test]
+fn test_xoroshiro1024_advance() {
+  let mut expected = xoroshiro1024_test_gen();
+  let mut gen = xoroshiro1024_test_gen();
+  gen.advance(0);
+  assert_eq!(gen, expected);
+  for &delta in [1, 15, 16, 1000].iter() {
+    for _ in 0 .. delta {
+      expected.next_u64();
+    }
+    gen.advance(delta);
+    assert_eq!(gen, expected);
+  }
+  gen.rewind(1032);
+  assert_eq!(gen, xoroshiro1024_test_gen());
+  // x^(2^512) = (((x^(2^127))^(2^127))^(2^127))^(2^127))^(2^4)
+  let mut poly = x_pow(1 << 127, &XOROSHIRO1024_CHARPOLY);
+  for &k in [1 << 127, 1 << 127, 1 << 127, 1 << 4].iter() {
+    poly = powmod(&poly, k, &XOROSHIRO1024_CHARPOLY);
+  }
+  assert_eq!(poly, XOROSHIRO1024_JUMP);
+}
+
+#[test]
+fn test_xorshiftplus128_advance() {
+  let mut gen = Xorshiftplus128v1Generator::from([1, 2]);
+  gen.advance(1 << 64);
+  let mut expected = Xorshiftplus128v1Generator::from([1, 2]);
+  expected.jump();
+  assert_eq!(gen, expected);
+  gen.advance((1 << 96) - (1 << 64));
+  expected = Xorshiftplus128v1Generator::from([1, 2]);
+  expected.long_jump();
+  assert_eq!(gen, expected);
+  gen.rewind(1 << 96);
+  assert_eq!(gen, Xorshiftplus128v1Generator::from([1, 2]));
+  let mut gen = Xorshiftplus128v2Generator::from([1, 2]);
+  gen.advance(1 << 96);
+  let mut expected = Xorshiftplus128v2Generator::from([1, 2]);
+  expected.long_jump();
+  assert_eq!(gen, expected);
+  let mut expected = Xorshiftplus128v2Generator::from([1, 2]);
+  for _ in 0 .. 100 {
+    expected.next_u64();
+  }
+  gen = Xorshiftplus128v2Generator::from([1, 2]);
+  gen.advance(123);
+  gen.rewind(23);
+  assert_eq!(gen, expected);
+}
+
+#[test]
+fn test_xorshiftplus128v1_advance_matches_stepping() {
+  let mut expected = Xorshiftplus128v1Generator::from([1, 2]);
+  let mut gen = Xorshiftplus128v1Generator::from([1, 2]);
+  gen.advance(0);
+  assert_eq!(gen, expected);
+  for &delta in [1, 2, 127, 128, 1000].iter() {
+    for _ in 0 .. delta {
+      expected.next_u64();
+    }
+    gen.advance(delta);
+    assert_eq!(gen, expected);
+  }
+  gen.rewind(1258);
+  assert_eq!(gen, Xorshiftplus128v1Generator::from([1, 2]));
+  for &delta in [1, 129, 1 << 100].iter() {
+    let mut gen = Xorshiftplus128v1Generator::from([3, 4]);
+    gen.advance(delta);
+    gen.rewind(delta);
+    assert_eq!(gen, Xorshiftplus128v1Generator::from([3, 4]));
+  }
+}
+
+#[test]
+fn test_xoroshiro1024_stream_seek() {
+  let mut stream = Xoroshiro1024Stream::new(xoroshiro1024_test_gen());
+  let mut expected = [0; 200];
+  stream.read_exact(&mut expected).unwrap();
+  // The test generator has already produced 5 words.
+  assert_eq!(stream.stream_position().unwrap(), 240);
+  stream.seek(SeekFrom::Start(40)).unwrap();
+  let mut buf = [0; 50];
+  assert_eq!(stream.seek(SeekFrom::Start(141)).unwrap(), 141);
+  stream.read_exact(&mut buf).unwrap();
+  assert_eq!(&buf[..], &expected[101 .. 151]);
+  assert_eq!(stream.seek(SeekFrom::Current(-147)).unwrap(), 44);
+  stream.read_exact(&mut buf).unwrap();
+  assert_eq!(&buf[..], &expected[4 .. 54]);
+  assert_eq!(stream.seek(SeekFrom::End(-8)).unwrap_err().kind(), IoErrorKind::InvalidInput);
+  let mut gen = xoroshiro1024_test_gen();
+  assert_eq!(gen.seek(SeekFrom::Start(3)).unwrap_err().kind(), IoErrorKind::InvalidInput);
+}
+
+fn check_outputs<G: Stream>(mut gen: G, expected: &[u64]) {
+  for &x in expected.iter() {
+    assert_eq!(gen.next_u64(), x);
+  }
+}
+
+#[test]
+fn test_xoshiro_reference() {
+  check_outputs(Xoshiro256plusplusGenerator::from([1, 2, 3, 4]), &[
+      41943041, 58720359, 3588806011781223, 3591011842654386, 9228616714210784205,
+      9973669472204895162, 14011001112246962877, 12406186145184390807, 15849039046786891736, 10450023813501588000,
+  ]);
+  check_outputs(Xoshiro256starstarGenerator::from([1, 2, 3, 4]), &[
+      11520, 0, 1509978240, 1215971899390074240, 1216172134540287360,
+      607988272756665600, 16172922978634559625, 8476171486693032832, 10595114339597558777, 2904607092377533576,
+  ]);
+  check_outputs(Xoshiro256plusGenerator::from([1, 2, 3, 4]), &[
+      5, 211106232532999, 211106635186183, 9223759065350669058, 9250833439874351877,
+      13862484359527728515, 2346507365006083650, 1168864526675804870, 34095955243042024, 3466914240207415127,
+  ]);
+  check_outputs(Xoroshiro128plusplusGenerator::from([1, 2]), &[
+      393217, 669327710093319, 1732421326133921491, 11394790081659126983, 9555452776773192676,
+      3586421180005889563, 1691397964866707553, 10735626796753111697, 15216282715349408991, 14247243556711267923,
+  ]);
+  check_outputs(Xoroshiro128starstarGenerator::from([1, 2]), &[
+      5760, 97769243520, 9706862127477703552, 9223447511460779954, 8358291023205304566,
+      15695619998649302768, 8517900938696309774, 16586480348202605369, 6959129367028440372, 16822147227405758281,
+  ]);
+  check_outputs(Xoroshiro128plusGenerator::from([1, 2]), &[
+      3, 412333834243, 2360170716294286339, 9295852285959843169, 2797080929874688578,
+      6019711933173041966, 3076529664176959358, 3521761819100106140, 7493067640054542992, 920801338098114767,
+  ]);
+  check_outputs(Xoshiro512plusplusGenerator::from([1, 2, 3, 4, 5, 6, 7, 8]), &[
+      524291, 1048578, 539099140, 3299073855497, 6917532603230064654,
+      7494048333530275843, 14418333309547923463, 10960079161595355914, 18279570946505382726, 10209173166699159237,
+  ]);
+  check_outputs(Xoshiro512starstarGenerator::from([1, 2, 3, 4, 5, 6, 7, 8]), &[
+      11520, 0, 23040, 23667840, 144955163520,
+      303992986974289920, 25332796375735680, 296904390158016, 13911081092387501979, 15304787717237593024,
+  ]);
+}
+
+#[test]
+fn test_xoroshiro1024_variants_reference() {
+  let mut state = [0; 16];
+  for (k, x) in state.iter_mut().enumerate() {
+    *x = k as u64 + 1;
+  }
+  let mut gen = Xoroshiro1024starGenerator::from(state);
+  for _ in 0 .. 100 {
+    gen.next_u64();
+  }
+  check_outputs(gen, &[0x3429db85a242c83d, 0x4a1d5389eab31ac4, 0xd1be9478674fdae1]);
+  let mut gen = Xoroshiro1024starstarGenerator::from(state);
+  for _ in 0 .. 100 {
+    gen.next_u64();
+  }
+  gen.advance(0);
+  check_outputs(gen, &[0xea804751ef0bc51f, 0xcd3c1cf2b97020fd, 0x92f67afbe6d3f04f]);
+}
+
+#[test]
+fn test_xoshiro_jump() {
+  let mut gen = Xoshiro256plusplusGenerator::from([1, 2, 3, 4]);
+  gen.jump();
+  check_outputs(gen, &[0xec879073673df437, 0x20d212a39aca1eaa, 0xc19d712a27e40f57]);
+  let mut gen = Xoshiro256plusplusGenerator::from([1, 2, 3, 4]);
+  gen.long_jump();
+  check_outputs(gen, &[0xb5c4ea370b330bf5, 0x5173cc693c0fa533, 0x1dc5df0151f7b491]);
+  let mut gen = Xoshiro256starstarGenerator::from([1, 2, 3, 4]);
+  gen.jump();
+  check_outputs(gen, &[0xbbd2f312298443d8, 0x62e57db2d5706577, 0x34d1890374a6d72b]);
+  let mut gen = Xoshiro256plusGenerator::from([1, 2, 3, 4]);
+  gen.jump();
+  check_outputs(gen, &[0x1000ccc01af67421, 0xaae59741dcb3a9e7, 0x5640f5e7f9a31526]);
+  let mut gen = Xoroshiro128plusplusGenerator::from([1, 2]);
+  gen.jump();
+  check_outputs(gen, &[0x6115ff4c07d8c03e, 0xf4564a51c7eab4b9, 0xfd85cda8113be346]);
+  let mut gen = Xoroshiro128plusplusGenerator::from([1, 2]);
+  gen.long_jump();
+  check_outputs(gen, &[0xbb077da55888837c, 0x3fd58ef899113160, 0x851ed84070f6f99c]);
+  let mut gen = Xoroshiro128starstarGenerator::from([1, 2]);
+  gen.jump();
+  check_outputs(gen, &[0x2232b5a1a6bd6889, 0xa105683719162dae, 0x0a2eda78a71cef3f]);
+  let mut gen = Xoroshiro128starstarGenerator::from([1, 2]);
+  gen.long_jump();
+  check_outputs(gen, &[0x100714ad00ea19d8, 0x54173fc144bd5c92, 0xd6880d1c0405ab88]);
+  let mut gen = Xoroshiro128plusGenerator::from([1, 2]);
+  gen.jump();
+  check_outputs(gen, &[0xea081299d29ad927, 0xdde2899549f899c8, 0xe9fbdbe2a1bfda9c]);
+  let mut gen = Xoshiro512plusplusGenerator::from([1, 2, 3, 4, 5, 6, 7, 8]);
+  gen.jump();
+  check_outputs(gen, &[0xb86339b7fc03fec0, 0xaa2dcb4cfd5495e3, 0x8c2661e04862ca27]);
+  let mut gen = Xoshiro512plusplusGenerator::from([1, 2, 3, 4, 5, 6, 7, 8]);
+  gen.long_jump();
+  check_outputs(gen, &[0xc5f80dd699c67e82, 0x795cfe51f6861a99, 0x95009f5fe32c8923]);
+  let mut gen = Xoshiro512starstarGenerator::from([1, 2, 3, 4, 5, 6, 7, 8]);
+  gen.jump();
+  check_outputs(gen, &[0x88c63daa2223c441, 0x788ad705a9e6c6f0, 0x2ef108991fa27a22]);
+}
+}